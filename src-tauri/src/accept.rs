@@ -0,0 +1,158 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// OS error codes of `accept()` when the process or the system is out of file descriptors.
+#[cfg(not(windows))]
+const EXHAUSTED: &[i32] = &[
+    23, // ENFILE
+    24, // EMFILE
+];
+#[cfg(windows)]
+const EXHAUSTED: &[i32] = &[
+    10024, // WSAEMFILE
+    10055, // WSAENOBUFS
+];
+
+/// Accepts the local connections of the bridge of `url`.
+///
+/// Errors that clear up on their own, a client that went away before it was accepted or running
+/// out of file descriptors, are waited out with a backoff instead of being retried in a hot loop
+/// or stopping the bridge. Running out is reported as a `bridge-warning` event, cleared once a
+/// connection is accepted again.
+pub struct Acceptor {
+    url: String,
+    failures: u32,
+    retry_at: Option<Instant>,
+    warned: bool,
+}
+
+impl Acceptor {
+    pub fn new(url: &str) -> Self {
+        Acceptor {
+            url: url.to_string(),
+            failures: 0,
+            retry_at: None,
+            warned: false,
+        }
+    }
+
+    /// `TcpListener::accept` that only returns the errors the listener does not recover from.
+    /// Cancel safe, a backoff cut short by another `select!` branch is resumed on the next call.
+    pub async fn accept(&mut self, listener: &TcpListener) -> io::Result<(TcpStream, SocketAddr)> {
+        loop {
+            if let Some(retry_at) = self.retry_at {
+                tokio::time::sleep_until(retry_at).await;
+                self.retry_at = None;
+            }
+
+            let error = match listener.accept().await {
+                Ok(accepted) => {
+                    if std::mem::take(&mut self.failures) > 0 {
+                        tracing::info!(url = self.url, "accepting connections again");
+                    }
+                    if std::mem::take(&mut self.warned) {
+                        crate::lifecycle::warn(&self.url, None);
+                    }
+                    return Ok(accepted);
+                }
+                Err(e) if is_transient(&e) => e,
+                Err(e) => return Err(e),
+            };
+
+            self.failures += 1;
+            let delay = backoff(self.failures);
+            self.retry_at = Some(Instant::now() + delay);
+
+            if is_exhausted(&error) {
+                tracing::warn!(url = self.url, ?delay, "out of file descriptors: {error}");
+                if !self.warned {
+                    self.warned = true;
+                    crate::lifecycle::warn(
+                        &self.url,
+                        Some(format!("not accepting connections: {error}")),
+                    );
+                }
+            } else {
+                tracing::debug!(url = self.url, ?delay, "failed to accept: {error}");
+            }
+        }
+    }
+}
+
+/// The wait after the `failures`th accept error in a row.
+fn backoff(failures: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+fn is_exhausted(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::OutOfMemory
+        || e.raw_os_error()
+            .is_some_and(|code| EXHAUSTED.contains(&code))
+}
+
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+    ) || is_exhausted(e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff(1), INITIAL_BACKOFF);
+        assert_eq!(backoff(2), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff(3), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff(8), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn running_out_of_descriptors_is_transient() {
+        for code in EXHAUSTED {
+            let e = io::Error::from_raw_os_error(*code);
+            assert!(is_exhausted(&e), "{e}");
+            assert!(is_transient(&e), "{e}");
+        }
+        assert!(is_exhausted(&io::Error::from(io::ErrorKind::OutOfMemory)));
+    }
+
+    #[test]
+    fn clients_going_away_are_transient() {
+        for kind in [
+            io::ErrorKind::ConnectionAborted,
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::Interrupted,
+            io::ErrorKind::WouldBlock,
+        ] {
+            let e = io::Error::from(kind);
+            assert!(is_transient(&e), "{e}");
+            assert!(!is_exhausted(&e), "{e}");
+        }
+    }
+
+    #[test]
+    fn other_errors_stop_the_bridge() {
+        for kind in [
+            io::ErrorKind::PermissionDenied,
+            io::ErrorKind::InvalidInput,
+            io::ErrorKind::Other,
+        ] {
+            assert!(!is_transient(&io::Error::from(kind)));
+        }
+    }
+}
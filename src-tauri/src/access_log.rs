@@ -0,0 +1,403 @@
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex, OnceLock, mpsc};
+use std::task::{Context, Poll, ready};
+
+/// Directory in the app data dir holding the access log files, one per bridge.
+const ACCESS_LOG_DIR: &str = "access-logs";
+/// How many requests of each bridge the in-app view can scroll back to.
+const CAPACITY: usize = 500;
+/// A bridge's file is rotated once it grows past this.
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Rotated files kept besides the current one, `<bridge>.log.1` being the newest.
+const MAX_ROTATED: usize = 2;
+
+/// Format of a bridge's access log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// `host ident authuser [date] "request" status bytes`
+    Common,
+    /// The common format followed by `"referer" "user-agent"`.
+    Combined,
+}
+
+/// A request proxied by an HTTP bridge, sent to the frontend as `access-log` events.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessEntry {
+    pub url: String,
+    /// Unix time the request came in, in milliseconds.
+    pub time: u64,
+    /// The local client.
+    pub client: String,
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    /// Unset if the peer could not be reached and the connection was dropped.
+    pub status: Option<u16>,
+    /// Body bytes sent to the client, counted as the response streams.
+    pub bytes: u64,
+    /// Until the response headers were back.
+    pub duration_ms: u64,
+    /// The id52 the request was proxied to, empty if it was answered without the peer.
+    pub peer: String,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+struct Bridge {
+    entries: VecDeque<AccessEntry>,
+    /// Lines for the file are written by a task of its own, so requests never wait for the disk.
+    file: Option<(Format, mpsc::Sender<String>)>,
+}
+
+static BRIDGES: LazyLock<Mutex<HashMap<String, Bridge>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Remembers where access log files go, called once from `setup`.
+pub fn init(app_handle: &tauri::AppHandle) -> eyre::Result<()> {
+    use tauri::Manager;
+
+    let dir = app_handle.path().app_data_dir()?.join(ACCESS_LOG_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let _ = DIR.set(dir);
+    Ok(())
+}
+
+/// Starts the access log of the bridge of `url` from scratch, also appending to a file in
+/// `format` if given.
+pub fn start(url: &str, format: Option<Format>) {
+    let file = format.and_then(|format| {
+        match path(url).and_then(|path| Writer::open(path, MAX_FILE_SIZE)) {
+            Ok(mut writer) => {
+                let (tx, rx) = mpsc::channel::<String>();
+                let url = url.to_string();
+                tokio::task::spawn_blocking(move || {
+                    // ends once the file is closed and its sender dropped
+                    for line in rx {
+                        if let Err(e) = writer.write(&line) {
+                            tracing::error!(url, "failed to write access log: {e:?}");
+                        }
+                    }
+                });
+                Some((format, tx))
+            }
+            Err(e) => {
+                tracing::error!(url, "failed to open access log file: {e:?}");
+                None
+            }
+        }
+    });
+    BRIDGES
+        .lock()
+        .expect("Unable to unlock access logs")
+        .insert(
+            url.to_string(),
+            Bridge {
+                entries: VecDeque::with_capacity(CAPACITY),
+                file,
+            },
+        );
+}
+
+/// Closes the access log file of the bridge of `url`, its entries stay viewable until it is
+/// started again.
+pub fn stop(url: &str) {
+    if let Some(bridge) = BRIDGES
+        .lock()
+        .expect("Unable to unlock access logs")
+        .get_mut(url)
+    {
+        bridge.file = None;
+    }
+}
+
+/// The requests of the bridge of `url`, oldest first.
+pub fn entries(url: &str) -> Vec<AccessEntry> {
+    BRIDGES
+        .lock()
+        .expect("Unable to unlock access logs")
+        .get(url)
+        .map(|bridge| bridge.entries.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn record(entry: AccessEntry) {
+    {
+        let mut bridges = BRIDGES.lock().expect("Unable to unlock access logs");
+        let Some(bridge) = bridges.get_mut(&entry.url) else {
+            return;
+        };
+        if let Some((format, tx)) = &bridge.file
+            && tx.send(entry.line(*format)).is_err()
+        {
+            tracing::error!(url = entry.url, "access log writer stopped");
+        }
+        if bridge.entries.len() == CAPACITY {
+            bridge.entries.pop_front();
+        }
+        bridge.entries.push_back(entry.clone());
+    }
+    crate::events::emit("access-log", entry);
+}
+
+/// Records `entry` with the status of `response` once its body is sent or dropped, with the
+/// bytes that went out until then.
+pub fn record_response(
+    mut entry: AccessEntry,
+    response: hyper::Response<BoxBody<Bytes, eyre::Error>>,
+) -> hyper::Response<BoxBody<Bytes, eyre::Error>> {
+    entry.status = Some(response.status().as_u16());
+    let (parts, body) = response.into_parts();
+    let body = Counted {
+        inner: body,
+        entry: Some(entry),
+    };
+    hyper::Response::from_parts(parts, BoxBody::new(body))
+}
+
+/// A response body recording its access log entry when it ends.
+struct Counted<B> {
+    inner: B,
+    entry: Option<AccessEntry>,
+}
+
+impl<B> Counted<B> {
+    fn finish(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            record(entry);
+        }
+    }
+}
+
+impl<B> Drop for Counted<B> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl<B> Body for Counted<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                let len = frame.data_ref().map_or(0, |data| data.len() as u64);
+                if let Some(entry) = self.entry.as_mut() {
+                    entry.bytes += len;
+                }
+            }
+            Some(Err(_)) | None => self.finish(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+fn path(url: &str) -> eyre::Result<PathBuf> {
+    let dir = DIR
+        .get()
+        .ok_or_else(|| eyre::anyhow!("access logs are not initialized"))?;
+    let name: String = url
+        .trim_start_matches("kulfi://")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    Ok(dir.join(format!("{name}.log")))
+}
+
+/// Appends lines to the access log file of a bridge, rotating it like `crate::log_files` once it
+/// grows past `max_size`.
+struct Writer {
+    path: PathBuf,
+    /// Closed while rotating, as open files can not be renamed on Windows.
+    file: Option<File>,
+    size: u64,
+    max_size: u64,
+}
+
+impl Writer {
+    fn open(path: PathBuf, max_size: u64) -> eyre::Result<Self> {
+        let (file, size) = open(&path)?;
+        Ok(Writer {
+            path,
+            file: Some(file),
+            size,
+            max_size,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> eyre::Result<()> {
+        if self.file.is_none() {
+            // the last rotation failed half way
+            let (file, size) = open(&self.path)?;
+            self.file = Some(file);
+            self.size = size;
+        }
+        let file = self.file.as_mut().expect("the file was just opened");
+        writeln!(file, "{line}")?;
+        self.size += line.len() as u64 + 1;
+
+        if self.size > self.max_size {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> eyre::Result<()> {
+        self.file = None;
+        let _ = std::fs::remove_file(rotated(&self.path, MAX_ROTATED));
+        for n in (1..MAX_ROTATED).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                std::fs::rename(from, rotated(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))?;
+
+        let (file, size) = open(&self.path)?;
+        self.file = Some(file);
+        self.size = size;
+        Ok(())
+    }
+}
+
+/// Opens an access log file for appending, along with its size.
+fn open(path: &Path) -> eyre::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{n}"));
+    PathBuf::from(rotated)
+}
+
+impl AccessEntry {
+    fn line(&self, format: Format) -> String {
+        let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_string());
+        let mut line = format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.client,
+            crate::utc::Utc::from_unix_ms(self.time).clf(),
+            self.method,
+            self.path,
+            self.version,
+            or_dash(self.status.map(|s| s.to_string())),
+            or_dash((self.bytes > 0).then(|| self.bytes.to_string())),
+        );
+        if format == Format::Combined {
+            line.push_str(&format!(
+                " \"{}\" \"{}\"",
+                or_dash(self.referer.as_deref().map(escape)),
+                or_dash(self.user_agent.as_deref().map(escape)),
+            ));
+        }
+        line
+    }
+}
+
+/// Escapes quotes and backslashes of a quoted field.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessEntry {
+        AccessEntry {
+            url: "kulfi://peer/app".to_string(),
+            // 2024-02-29T13:05:09.250Z
+            time: 1_709_211_909_250,
+            client: "127.0.0.1".to_string(),
+            method: "GET".to_string(),
+            path: "/index.html?q=1".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: Some(200),
+            bytes: 1234,
+            duration_ms: 12,
+            peer: "peer".to_string(),
+            referer: Some("http://127.0.0.1:8080/".to_string()),
+            user_agent: Some("curl/8.0 \"quoted\" \\ path".to_string()),
+        }
+    }
+
+    #[test]
+    fn common_line() {
+        assert_eq!(
+            entry().line(Format::Common),
+            "127.0.0.1 - - [29/Feb/2024:13:05:09 +0000] \"GET /index.html?q=1 HTTP/1.1\" 200 1234"
+        );
+    }
+
+    #[test]
+    fn combined_line() {
+        assert_eq!(
+            entry().line(Format::Combined),
+            "127.0.0.1 - - [29/Feb/2024:13:05:09 +0000] \"GET /index.html?q=1 HTTP/1.1\" 200 1234 \
+             \"http://127.0.0.1:8080/\" \"curl/8.0 \\\"quoted\\\" \\\\ path\""
+        );
+    }
+
+    #[test]
+    fn dashes_for_missing_values() {
+        let entry = AccessEntry {
+            status: None,
+            bytes: 0,
+            referer: None,
+            user_agent: None,
+            ..entry()
+        };
+        assert_eq!(
+            entry.line(Format::Combined),
+            "127.0.0.1 - - [29/Feb/2024:13:05:09 +0000] \"GET /index.html?q=1 HTTP/1.1\" - - \"-\" \"-\""
+        );
+    }
+
+    #[test]
+    fn writer_rotates() {
+        let dir = std::env::temp_dir().join(format!("malai-access-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bridge.log");
+
+        let mut writer = Writer::open(path.clone(), 10).unwrap();
+        for line in ["first line", "second line", "third line", "fourth"] {
+            writer.write(line).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&rotated(&path, 1)), "third line\n");
+        assert_eq!(read(&rotated(&path, 2)), "second line\n");
+        assert!(!rotated(&path, 3).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// Key the network settings are persisted under in the profile's store.
+const SETTINGS_KEY: &str = "network";
+
+/// Which paths the endpoint may use to reach peers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathMode {
+    #[default]
+    Default,
+    /// Only talk to other hosts through a relay.
+    RelayOnly,
+    /// Never use a relay, peers must be reachable directly.
+    DirectOnly,
+}
+
+/// Settings of the iroh endpoint bridges of a profile dial from.
+///
+/// Empty lists and `None` mean the n0 defaults, so the default settings behave like
+/// `kulfi_utils::global_iroh_endpoint`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NetworkSettings {
+    /// Relay servers used instead of the n0 relays.
+    pub relay_urls: Vec<String>,
+    /// pkarr relays our address is published to and peers are resolved from, instead of n0's.
+    pub pkarr_urls: Vec<String>,
+    /// Origin domain for DNS discovery, instead of n0's.
+    pub dns_origin: Option<String>,
+    /// Discover peers on the local network.
+    pub mdns: bool,
+    pub mode: PathMode,
+    /// Local UDP port the endpoint binds, a random one if `None`.
+    pub bind_port: Option<u16>,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            relay_urls: vec![],
+            pkarr_urls: vec![],
+            dns_origin: None,
+            mdns: true,
+            mode: PathMode::Default,
+            bind_port: None,
+        }
+    }
+}
+
+/// The network settings of every profile that changed them, by profile name.
+static SETTINGS: LazyLock<Mutex<HashMap<String, NetworkSettings>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Why the persisted network settings of a profile could not be loaded, by profile name. Such a
+/// profile has no endpoint until valid settings are saved, so bridges never dial through the
+/// default servers in place of the ones the user chose.
+static LOAD_ERRORS: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The endpoint of every profile in use, built from its settings and identity on first use.
+static ENDPOINTS: LazyLock<tokio::sync::Mutex<HashMap<String, iroh::Endpoint>>> =
+    LazyLock::new(Default::default);
+
+pub fn settings(profile: &str) -> NetworkSettings {
+    SETTINGS
+        .lock()
+        .expect("Unable to unlock network settings")
+        .get(profile)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Why the network settings of `profile` could not be loaded, if they could not.
+pub fn load_error(profile: &str) -> Option<String> {
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock network settings load errors")
+        .get(profile)
+        .cloned()
+}
+
+/// The endpoint bridges dial from as `profile`, used in place of
+/// `kulfi_utils::global_iroh_endpoint`.
+///
+/// If the endpoint can not be built with the profile's settings, e.g. because the configured
+/// port is busy, this fails rather than reaching peers through infrastructure the user did not
+/// choose.
+pub async fn get(profile: &str) -> eyre::Result<iroh::Endpoint> {
+    let mut endpoints = ENDPOINTS.lock().await;
+    if let Some(endpoint) = endpoints.get(profile)
+        && !endpoint.is_closed()
+    {
+        return Ok(endpoint.clone());
+    }
+
+    if let Some(error) = load_error(profile) {
+        return Err(eyre::anyhow!(
+            "the network settings of profile {profile} could not be loaded: {error}"
+        ));
+    }
+    let secret_key = crate::identity::secret_key(profile)?;
+    let endpoint = build(&settings(profile), secret_key)
+        .await
+        .inspect_err(|e| {
+            tracing::error!(
+                profile,
+                "failed to create endpoint from network settings: {e:?}"
+            )
+        })?;
+    endpoints.insert(profile.to_string(), endpoint.clone());
+    Ok(endpoint)
+}
+
+/// Switches a profile to `settings`, rebuilding its endpoint if one is in use.
+///
+/// Connections of running bridges are dropped, new connections use the new endpoint. If the new
+/// endpoint can not be built the previous settings stay in effect. Valid settings replace
+/// settings that could not be loaded.
+pub async fn apply_settings(profile: &str, settings: NetworkSettings) -> eyre::Result<()> {
+    Parsed::new(&settings)?;
+    rebuild(profile, &settings).await?;
+
+    tracing::info!(profile, ?settings, "network settings applied");
+    SETTINGS
+        .lock()
+        .expect("Unable to unlock network settings")
+        .insert(profile.to_string(), settings);
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock network settings load errors")
+        .remove(profile);
+    Ok(())
+}
+
+/// Rebuilds the endpoint of a profile, if one is in use, e.g. to dial from a new identity.
+pub async fn restart(profile: &str) -> eyre::Result<()> {
+    rebuild(profile, &settings(profile)).await
+}
+
+async fn rebuild(profile: &str, settings: &NetworkSettings) -> eyre::Result<()> {
+    let mut endpoints = ENDPOINTS.lock().await;
+    let Some(old) = endpoints.remove(profile) else {
+        return Ok(());
+    };
+
+    // close first, the new endpoint may want to bind the same port
+    old.close().await;
+    let secret_key = crate::identity::secret_key(profile)?;
+    match build(settings, secret_key).await {
+        Ok(endpoint) => {
+            endpoints.insert(profile.to_string(), endpoint);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(profile, "failed to rebuild endpoint: {e:?}");
+            Err(e)
+        }
+    }
+}
+
+/// Closes the endpoint of a profile, the next `get` builds a new one.
+pub async fn close(profile: &str) {
+    let endpoint = ENDPOINTS.lock().await.remove(profile);
+    if let Some(endpoint) = endpoint {
+        endpoint.close().await;
+    }
+}
+
+/// Tells every endpoint in use that the network changed, so it rebinds its sockets and re-probes
+/// the paths to its peers.
+pub async fn network_change() {
+    let endpoints: Vec<iroh::Endpoint> = ENDPOINTS.lock().await.values().cloned().collect();
+    for endpoint in endpoints {
+        endpoint.network_change().await;
+    }
+}
+
+pub fn forget(profile: &str) {
+    SETTINGS
+        .lock()
+        .expect("Unable to unlock network settings")
+        .remove(profile);
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock network settings load errors")
+        .remove(profile);
+}
+
+/// Loads the persisted settings of a profile, called from `crate::profile::load` before any
+/// endpoint is built.
+///
+/// If that fails the error is reported by `load_error` and the profile's bridges fail to start
+/// until valid settings are saved.
+pub fn load_settings(app_handle: &tauri::AppHandle, profile: &str) {
+    match load_settings_(app_handle, profile) {
+        Ok(Some(settings)) => {
+            SETTINGS
+                .lock()
+                .expect("Unable to unlock network settings")
+                .insert(profile.to_string(), settings);
+        }
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!(profile, "failed to load network settings: {e:?}");
+            LOAD_ERRORS
+                .lock()
+                .expect("Unable to unlock network settings load errors")
+                .insert(profile.to_string(), format!("{e:#}"));
+        }
+    }
+}
+
+fn load_settings_(
+    app_handle: &tauri::AppHandle,
+    profile: &str,
+) -> eyre::Result<Option<NetworkSettings>> {
+    use tauri_plugin_store::StoreExt;
+
+    let store = app_handle.store(crate::profile::store_path(profile))?;
+    let Some(settings) = store.get(SETTINGS_KEY) else {
+        return Ok(None);
+    };
+    let settings: NetworkSettings = serde_json::from_value(settings)?;
+    Parsed::new(&settings)?;
+    Ok(Some(settings))
+}
+
+pub fn save_settings(
+    app_handle: &tauri::AppHandle,
+    profile: &str,
+    settings: &NetworkSettings,
+) -> eyre::Result<()> {
+    use tauri_plugin_store::StoreExt;
+
+    let store = app_handle.store(crate::profile::store_path(profile))?;
+    store.set(SETTINGS_KEY, serde_json::to_value(settings)?);
+    store.save()?;
+    Ok(())
+}
+
+/// `NetworkSettings` with the URLs parsed, so invalid settings are rejected before the current
+/// endpoint is closed.
+struct Parsed {
+    relay_urls: Vec<iroh::RelayUrl>,
+    pkarr_urls: Vec<url::Url>,
+}
+
+impl Parsed {
+    fn new(settings: &NetworkSettings) -> eyre::Result<Self> {
+        let relay_urls = settings
+            .relay_urls
+            .iter()
+            .map(|u| {
+                u.parse()
+                    .map_err(|e| eyre::anyhow!("invalid relay URL {u:?}: {e}"))
+            })
+            .collect::<eyre::Result<_>>()?;
+        let pkarr_urls = settings
+            .pkarr_urls
+            .iter()
+            .map(|u| {
+                u.parse()
+                    .map_err(|e| eyre::anyhow!("invalid pkarr URL {u:?}: {e}"))
+            })
+            .collect::<eyre::Result<_>>()?;
+
+        if settings.mode == PathMode::DirectOnly && !settings.relay_urls.is_empty() {
+            return Err(eyre::anyhow!(
+                "relay URLs can not be used in direct-only mode"
+            ));
+        }
+
+        Ok(Parsed {
+            relay_urls,
+            pkarr_urls,
+        })
+    }
+}
+
+/// A discovery service of an endpoint.
+#[derive(Debug, Clone, PartialEq)]
+enum Discovery {
+    N0Pkarr,
+    /// Publishes to and resolves from a self-hosted pkarr relay.
+    Pkarr(url::Url),
+    N0Dns,
+    Dns(String),
+    Mdns,
+}
+
+impl Parsed {
+    fn relay_mode(&self, settings: &NetworkSettings) -> iroh::RelayMode {
+        match settings.mode {
+            PathMode::DirectOnly => iroh::RelayMode::Disabled,
+            _ if self.relay_urls.is_empty() => iroh::RelayMode::Default,
+            _ => iroh::RelayMode::Custom(self.relay_urls.iter().cloned().collect()),
+        }
+    }
+
+    /// The discovery services besides the static address hints. n0's services are only used
+    /// where no self-hosted one replaces them.
+    fn discovery(&self, settings: &NetworkSettings) -> Vec<Discovery> {
+        let mut discovery = vec![];
+        if self.pkarr_urls.is_empty() {
+            discovery.push(Discovery::N0Pkarr);
+        }
+        discovery.extend(self.pkarr_urls.iter().cloned().map(Discovery::Pkarr));
+
+        match &settings.dns_origin {
+            Some(origin) => discovery.push(Discovery::Dns(origin.clone())),
+            None if self.pkarr_urls.is_empty() => discovery.push(Discovery::N0Dns),
+            // self-hosted pkarr relays without a DNS origin: do not fall back to n0's DNS
+            None => {}
+        }
+
+        if settings.mdns && settings.mode != PathMode::RelayOnly {
+            discovery.push(Discovery::Mdns);
+        }
+        discovery
+    }
+}
+
+async fn build(
+    settings: &NetworkSettings,
+    secret_key: iroh::SecretKey,
+) -> eyre::Result<iroh::Endpoint> {
+    use iroh::discovery::{
+        dns::DnsDiscovery,
+        mdns::MdnsDiscovery,
+        pkarr::{PkarrPublisher, PkarrResolver},
+    };
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+
+    let parsed = Parsed::new(settings)?;
+
+    let mut builder = iroh::Endpoint::empty_builder(parsed.relay_mode(settings))
+        .secret_key(secret_key)
+        .alpns(vec![kulfi_utils::APNS_IDENTITY.into()])
+        .discovery(crate::peer_addr::static_provider());
+
+    for discovery in parsed.discovery(settings) {
+        builder = match discovery {
+            Discovery::N0Pkarr => builder.discovery(PkarrPublisher::n0_dns()),
+            Discovery::Pkarr(pkarr_url) => builder
+                .discovery(PkarrPublisher::builder(pkarr_url.clone()))
+                .discovery(PkarrResolver::builder(pkarr_url)),
+            Discovery::N0Dns => builder.discovery(DnsDiscovery::n0_dns()),
+            Discovery::Dns(origin) => builder.discovery(DnsDiscovery::builder(origin)),
+            Discovery::Mdns => builder.discovery(MdnsDiscovery::builder()),
+        };
+    }
+
+    // iroh has no public switch to only use relays, so bind the UDP sockets to loopback instead:
+    // the relay connection is then the only way to reach other hosts.
+    let (ipv4, ipv6) = match settings.mode {
+        PathMode::RelayOnly => (Ipv4Addr::LOCALHOST, Ipv6Addr::LOCALHOST),
+        _ => (Ipv4Addr::UNSPECIFIED, Ipv6Addr::UNSPECIFIED),
+    };
+    if settings.mode == PathMode::RelayOnly || settings.bind_port.is_some() {
+        let port = settings.bind_port.unwrap_or(0);
+        builder = builder
+            .bind_addr_v4(SocketAddrV4::new(ipv4, port))
+            .bind_addr_v6(SocketAddrV6::new(ipv6, port, 0, 0));
+    }
+
+    builder
+        .bind()
+        .await
+        .map_err(|e| eyre::anyhow!("failed to bind iroh endpoint: {e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(relay_urls: &[&str], pkarr_urls: &[&str]) -> NetworkSettings {
+        NetworkSettings {
+            relay_urls: relay_urls.iter().map(|u| u.to_string()).collect(),
+            pkarr_urls: pkarr_urls.iter().map(|u| u.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_urls() {
+        assert!(Parsed::new(&settings(&["not a url"], &[])).is_err());
+        assert!(Parsed::new(&settings(&[], &["not a url"])).is_err());
+        assert!(
+            Parsed::new(&settings(
+                &["http://127.0.0.1:3340"],
+                &["http://127.0.0.1:8080"]
+            ))
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn rejects_relays_in_direct_only_mode() {
+        let settings = NetworkSettings {
+            mode: PathMode::DirectOnly,
+            ..settings(&["http://127.0.0.1:3340"], &[])
+        };
+        assert!(Parsed::new(&settings).is_err());
+    }
+
+    #[test]
+    fn relay_mode_follows_settings() {
+        let default = settings(&[], &[]);
+        let parsed = Parsed::new(&default).unwrap();
+        assert!(matches!(
+            parsed.relay_mode(&default),
+            iroh::RelayMode::Default
+        ));
+
+        let custom = settings(&["http://127.0.0.1:3340"], &[]);
+        let parsed = Parsed::new(&custom).unwrap();
+        assert!(matches!(
+            parsed.relay_mode(&custom),
+            iroh::RelayMode::Custom(_)
+        ));
+
+        let direct = NetworkSettings {
+            mode: PathMode::DirectOnly,
+            ..default
+        };
+        let parsed = Parsed::new(&direct).unwrap();
+        assert!(matches!(
+            parsed.relay_mode(&direct),
+            iroh::RelayMode::Disabled
+        ));
+    }
+
+    #[test]
+    fn default_discovery_is_n0_and_mdns() {
+        let settings = settings(&[], &[]);
+        let parsed = Parsed::new(&settings).unwrap();
+        assert_eq!(
+            parsed.discovery(&settings),
+            vec![Discovery::N0Pkarr, Discovery::N0Dns, Discovery::Mdns]
+        );
+    }
+
+    #[test]
+    fn self_hosted_pkarr_replaces_n0() {
+        let settings = NetworkSettings {
+            mdns: false,
+            ..settings(&[], &["http://127.0.0.1:8080"])
+        };
+        let parsed = Parsed::new(&settings).unwrap();
+        assert_eq!(
+            parsed.discovery(&settings),
+            vec![Discovery::Pkarr("http://127.0.0.1:8080".parse().unwrap())]
+        );
+
+        let settings = NetworkSettings {
+            dns_origin: Some("dns.example.test".to_string()),
+            ..settings
+        };
+        assert_eq!(
+            parsed.discovery(&settings),
+            vec![
+                Discovery::Pkarr("http://127.0.0.1:8080".parse().unwrap()),
+                Discovery::Dns("dns.example.test".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn relay_only_skips_mdns() {
+        let settings = NetworkSettings {
+            mode: PathMode::RelayOnly,
+            ..settings(&[], &[])
+        };
+        let parsed = Parsed::new(&settings).unwrap();
+        assert!(!parsed.discovery(&settings).contains(&Discovery::Mdns));
+    }
+}
@@ -0,0 +1,136 @@
+/// The kind of bridge a `UrlEntry` starts, mirrors `ConnectionType` in the frontend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionType {
+    #[default]
+    Http,
+    Tcp,
+    Udp,
+    TcpUdp,
+}
+
+impl ConnectionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionType::Http => "http",
+            ConnectionType::Tcp => "tcp",
+            ConnectionType::Udp => "udp",
+            ConnectionType::TcpUdp => "tcp-udp",
+        }
+    }
+}
+
+impl std::str::FromStr for ConnectionType {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "http" => Ok(ConnectionType::Http),
+            "tcp" => Ok(ConnectionType::Tcp),
+            "udp" => Ok(ConnectionType::Udp),
+            "tcp-udp" => Ok(ConnectionType::TcpUdp),
+            _ => Err(eyre::anyhow!("unknown connection type: {s}")),
+        }
+    }
+}
+
+/// One URL entry of an item, as stored by the frontend.
+///
+/// Every setting of the entry has to be here, share links only carry what serde keeps.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlEntry {
+    pub url: String,
+    pub port: Option<u16>,
+    #[serde(rename = "type", default)]
+    pub kind: ConnectionType,
+    #[serde(default = "default_open_in_browser")]
+    pub open_in_browser: bool,
+    #[serde(flatten)]
+    pub hints: AddrHints,
+    /// Dial the peer after the bridge starts and report whether it answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preflight: Option<bool>,
+    /// Profile whose identity the bridge dials from, the active one if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// How TCP and UDP bridges retry opening a stream to the peer, the default if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<crate::retry::RetryPolicy>,
+    /// Other `kulfi://` URLs of the same service, tried in order when the peer of `url` is
+    /// unreachable.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallbacks: Vec<String>,
+    /// While a fallback is active, how often the primary is probed to switch back to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failback_secs: Option<u64>,
+    /// How new connections are spread over the peer of `url` and its fallbacks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<crate::failover::Strategy>,
+    /// How often every peer is dialed, the ones that do not answer are left out until they do.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check_secs: Option<u64>,
+    /// Connect to the peer only when a client shows up and let go after this long without
+    /// traffic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_secs: Option<u64>,
+    /// When the bridge is started again after it failed, the default if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<crate::supervisor::RestartPolicy>,
+    /// Also write the requests of an HTTP bridge to a file in this format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<crate::access_log::Format>,
+    /// Capture the requests and responses of an HTTP bridge for the inspector.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inspect: Option<bool>,
+    /// Record an HTTP bridge's session to a HAR file, or answer from one without the peer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub har: Option<crate::har::HarOptions>,
+    /// Timeouts and request size limit of an HTTP bridge, none if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_limits: Option<crate::http_limits::HttpLimits>,
+    /// Origins the peer's web app redirects to or scopes its cookies to, rewritten to the
+    /// bridge.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rewrite_origins: Vec<String>,
+    /// Sent as `Host` to the peer instead of the bridge's address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_override: Option<String>,
+    /// Added to every request of an HTTP bridge, as `Name: value`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_headers: Vec<String>,
+    /// Names of request headers an HTTP bridge does not forward.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strip_headers: Vec<String>,
+}
+
+/// Ways to reach a peer without a discovery service, e.g. on an isolated LAN.
+///
+/// Either an iroh endpoint ticket, or explicit direct addresses and a relay URL, or both.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddrHints {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub direct_addrs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_url: Option<String>,
+}
+
+impl AddrHints {
+    pub fn is_empty(&self) -> bool {
+        self.ticket.is_none() && self.direct_addrs.is_empty() && self.relay_url.is_none()
+    }
+}
+
+fn default_open_in_browser() -> bool {
+    true
+}
+
+/// A named item with its URL entries, the part of the frontend `ItemConfig` that can be shared.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SharedItem {
+    pub name: String,
+    pub urls: Vec<UrlEntry>,
+}
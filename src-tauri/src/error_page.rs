@@ -0,0 +1,123 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::StatusCode;
+use hyper::body::Bytes;
+
+/// Why an HTTP bridge could not proxy a request, shown to the browser as a small HTML page.
+#[derive(Debug)]
+pub struct ErrorPage {
+    pub status: StatusCode,
+    /// The id52 the request was for, if it got that far.
+    pub peer: Option<String>,
+    /// One sentence saying what went wrong.
+    pub summary: String,
+    pub error: String,
+    /// Where the retry link goes, the path and query of the failed request.
+    pub retry: String,
+}
+
+impl ErrorPage {
+    pub fn response(&self) -> hyper::Response<BoxBody<Bytes, eyre::Error>> {
+        let title = format!(
+            "{} {}",
+            self.status.as_u16(),
+            self.status.canonical_reason().unwrap_or_default()
+        );
+        let peer = self
+            .peer
+            .as_deref()
+            .map(|peer| format!("<p>Peer: <code>{}</code></p>\n", escape(peer)))
+            .unwrap_or_default();
+        let html = format!(
+            "<!DOCTYPE html>\n\
+             <html>\n\
+             <head><meta charset=\"utf-8\"><title>{title}</title></head>\n\
+             <body style=\"font-family: sans-serif; max-width: 40em; margin: 3em auto;\">\n\
+             <h1>{title}</h1>\n\
+             <p>{summary}</p>\n\
+             {peer}\
+             <pre style=\"white-space: pre-wrap;\">{error}</pre>\n\
+             <p><a href=\"{retry}\">Retry</a></p>\n\
+             <hr><small>malai-client</small>\n\
+             </body>\n\
+             </html>\n",
+            title = escape(&title),
+            summary = escape(&self.summary),
+            error = escape(&self.error),
+            retry = escape(&retry_href(&self.retry)),
+        );
+
+        let mut response = hyper::Response::new(
+            Full::new(Bytes::from(html))
+                .map_err(|never| match never {})
+                .boxed(),
+        );
+        *response.status_mut() = self.status;
+        let headers = response.headers_mut();
+        headers.insert(
+            hyper::header::CONTENT_TYPE,
+            hyper::header::HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        headers.insert(
+            hyper::header::CACHE_CONTROL,
+            hyper::header::HeaderValue::from_static("no-store"),
+        );
+        response
+    }
+}
+
+/// Whether `e` is a timeout anywhere along its chain, iroh and kulfi_utils only report some as
+/// text.
+pub fn is_timeout(e: &eyre::Error) -> bool {
+    e.chain().any(|cause| {
+        cause.is::<tokio::time::error::Elapsed>()
+            || cause
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+            || cause.to_string().to_lowercase().contains("timed out")
+    })
+}
+
+/// `path` as a link on this bridge, a path starting with several slashes or backslashes would
+/// otherwise be read by the browser as a link to another host.
+fn retry_href(path: &str) -> String {
+    format!("/{}", path.trim_start_matches(['/', '\\']))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_stays_on_the_bridge() {
+        assert_eq!(retry_href("/api/items?page=2"), "/api/items?page=2");
+        assert_eq!(retry_href("//evil.example/x"), "/evil.example/x");
+        assert_eq!(retry_href("/\\evil.example/x"), "/evil.example/x");
+        assert_eq!(retry_href("\\\\evil.example"), "/evil.example");
+        assert_eq!(retry_href(""), "/");
+    }
+
+    #[tokio::test]
+    async fn page_links_the_collapsed_path() {
+        let page = ErrorPage {
+            status: StatusCode::BAD_GATEWAY,
+            peer: None,
+            summary: "The peer could not be reached.".to_string(),
+            error: "connection refused".to_string(),
+            retry: "//evil.example/x?a=1&b=2".to_string(),
+        };
+        let response = page.response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let html = std::str::from_utf8(&body).unwrap();
+        assert!(html.contains("<a href=\"/evil.example/x?a=1&amp;b=2\">Retry</a>"));
+        assert!(!html.contains("//evil.example"));
+    }
+}
@@ -0,0 +1,18 @@
+use std::sync::OnceLock;
+use tauri::Emitter;
+
+static APP_HANDLE: OnceLock<tauri::AppHandle> = OnceLock::new();
+
+/// Remembers the app handle so background tasks can emit events, called once from `setup`.
+pub fn init(app_handle: tauri::AppHandle) {
+    let _ = APP_HANDLE.set(app_handle);
+}
+
+/// Emits `payload` to the frontend, does nothing before `init`.
+pub fn emit<S: serde::Serialize + Clone>(event: &str, payload: S) {
+    if let Some(app_handle) = APP_HANDLE.get()
+        && let Err(e) = app_handle.emit(event, payload)
+    {
+        tracing::error!(event, "failed to emit event: {e:?}");
+    }
+}
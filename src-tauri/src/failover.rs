@@ -0,0 +1,501 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+/// How a bridge spreads new connections over its peers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Stay on one peer, the primary first, and move to the next one only when it fails.
+    #[default]
+    Failover,
+    RoundRobin,
+    LeastConnections,
+}
+
+/// Other peers serving the same service as an entry, as `kulfi://` URLs.
+///
+/// With the failover strategy they are tried in order when the primary cannot be reached, and
+/// with `failback_secs` set the primary is probed that often while a fallback is active. A peer
+/// that fails a dial is left out until it answers again, with `health_check_secs` set every peer
+/// is dialed that often to find out. Balanced bridges check every `DEFAULT_HEALTH_CHECK` unless
+/// `health_check_secs` says otherwise, so a failed peer rejoins the rotation.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fallbacks {
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub strategy: Strategy,
+    pub failback_secs: Option<u64>,
+    pub health_check_secs: Option<u64>,
+}
+
+impl Fallbacks {
+    pub fn failback(&self) -> Option<Duration> {
+        secs(self.failback_secs)
+    }
+
+    pub fn health_check(&self) -> Option<Duration> {
+        secs(self.health_check_secs)
+    }
+}
+
+/// How often the peers of a balanced bridge are checked if the entry does not say.
+const DEFAULT_HEALTH_CHECK: Duration = Duration::from_secs(30);
+
+fn secs(secs: Option<u64>) -> Option<Duration> {
+    secs.filter(|secs| *secs > 0).map(Duration::from_secs)
+}
+
+/// The peer a bridge with fallbacks currently sends new connections to, sent to the frontend as
+/// `active-peer` events.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivePeer {
+    pub url: String,
+    pub id52: String,
+    pub primary: bool,
+    pub strategy: Strategy,
+    /// Peers failing their health check, left out of the rotation.
+    pub unhealthy: Vec<String>,
+}
+
+/// The ordered peers a bridge can reach its service on, primary first.
+///
+/// Clones share their state, so every connection of the bridge follows a switch and counts
+/// towards the same totals.
+#[derive(Debug, Clone)]
+pub struct Peers {
+    url: String,
+    id52s: Arc<[String]>,
+    strategy: Strategy,
+    active: Arc<AtomicUsize>,
+    /// Next peer to start from in round-robin mode.
+    next: Arc<AtomicUsize>,
+    connections: Arc<[AtomicUsize]>,
+    healthy: Arc<[AtomicBool]>,
+}
+
+/// Counts a connection to one of the `Peers` until dropped.
+#[derive(Debug)]
+pub struct Lease {
+    connections: Arc<[AtomicUsize]>,
+    index: usize,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.connections[self.index].fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Peers {
+    /// `id52s` must not be empty.
+    pub fn new(url: &str, id52s: Vec<String>, strategy: Strategy) -> Self {
+        assert!(!id52s.is_empty(), "a bridge needs at least one peer");
+        Peers {
+            url: url.to_string(),
+            strategy,
+            active: Arc::new(AtomicUsize::new(0)),
+            next: Arc::new(AtomicUsize::new(0)),
+            connections: id52s.iter().map(|_| AtomicUsize::new(0)).collect(),
+            healthy: id52s.iter().map(|_| AtomicBool::new(true)).collect(),
+            id52s: id52s.into(),
+        }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.strategy != Strategy::Failover
+    }
+
+    pub fn has_fallbacks(&self) -> bool {
+        self.id52s.len() > 1
+    }
+
+    /// Every peer, the primary first.
+    pub fn id52s(&self) -> &[String] {
+        &self.id52s
+    }
+
+    pub fn active(&self) -> &str {
+        &self.id52s[self.active.load(Ordering::SeqCst)]
+    }
+
+    fn status(&self) -> ActivePeer {
+        let active = self.active.load(Ordering::SeqCst);
+        ActivePeer {
+            url: self.url.clone(),
+            id52: self.id52s[active].clone(),
+            primary: active == 0,
+            strategy: self.strategy,
+            unhealthy: self
+                .id52s
+                .iter()
+                .zip(self.healthy.iter())
+                .filter(|(_, healthy)| !healthy.load(Ordering::SeqCst))
+                .map(|(id52, _)| id52.clone())
+                .collect(),
+        }
+    }
+
+    fn set_active(&self, index: usize) {
+        if self.strategy == Strategy::Failover && self.active.swap(index, Ordering::SeqCst) != index
+        {
+            tracing::info!(url = self.url, peer = self.id52s[index], "switched peer");
+            crate::events::emit("active-peer", self.status());
+        }
+    }
+
+    /// The order in which to try the peers for a new connection: by strategy among the healthy
+    /// peers, then the unhealthy ones as a last resort.
+    fn order(&self) -> Vec<usize> {
+        let n = self.id52s.len();
+        let mut order: Vec<usize> = match self.strategy {
+            Strategy::Failover => {
+                let active = self.active.load(Ordering::SeqCst);
+                std::iter::once(active)
+                    .chain((0..n).filter(|index| *index != active))
+                    .collect()
+            }
+            Strategy::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::SeqCst);
+                (0..n).map(|offset| (start + offset) % n).collect()
+            }
+            Strategy::LeastConnections => {
+                let mut order: Vec<usize> = (0..n).collect();
+                order.sort_by_key(|index| self.connections[*index].load(Ordering::SeqCst));
+                order
+            }
+        };
+        order.sort_by_key(|index| !self.healthy[*index].load(Ordering::SeqCst));
+        order
+    }
+
+    fn lease(&self, index: usize) -> Lease {
+        self.connections[index].fetch_add(1, Ordering::SeqCst);
+        Lease {
+            connections: self.connections.clone(),
+            index,
+        }
+    }
+
+    /// Picks the peer for a new connection without dialing it, relying on the health checks.
+    pub fn pick(&self) -> (String, Lease) {
+        let index = self.order()[0];
+        (self.id52s[index].clone(), self.lease(index))
+    }
+
+    /// Runs `dial` against the peers in the order of the strategy until one succeeds. With the
+    /// failover strategy the peer that answered becomes the active one. Peers that fail are
+    /// marked unhealthy, so the next connections try them last.
+    pub async fn dial<T, F, Fut>(&self, mut dial: F) -> eyre::Result<(T, Lease)>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = eyre::Result<T>>,
+    {
+        let mut last_error = None;
+        for index in self.order() {
+            let lease = self.lease(index);
+            match dial(self.id52s[index].clone()).await {
+                Ok(v) => {
+                    self.set_healthy(index, true);
+                    self.set_active(index);
+                    return Ok((v, lease));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        url = self.url,
+                        peer = self.id52s[index],
+                        "failed to reach peer: {e:?}"
+                    );
+                    self.set_healthy(index, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.expect("a bridge has at least one peer"))
+    }
+
+    /// Reports whether a connection to a `pick`ed peer got through, so a failed peer is tried
+    /// last until it answers again.
+    pub fn report(&self, id52: &str, reachable: bool) {
+        if let Some(index) = self.id52s.iter().position(|peer| peer == id52) {
+            self.set_healthy(index, reachable);
+        }
+    }
+
+    fn set_healthy(&self, index: usize, healthy: bool) {
+        if self.healthy[index].swap(healthy, Ordering::SeqCst) != healthy {
+            tracing::info!(
+                url = self.url,
+                peer = self.id52s[index],
+                healthy,
+                "peer health changed"
+            );
+            crate::events::emit("active-peer", self.status());
+        }
+    }
+}
+
+/// A running bridge with fallbacks, and its failback and health check tasks.
+struct Watched {
+    peers: Peers,
+    tasks: Vec<tokio::task::JoinHandle<()>>,
+}
+
+static WATCHED: LazyLock<Mutex<HashMap<String, Watched>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Starts reporting the active peer of a bridge that just started. If `failback` is set the
+/// primary is probed that often while a fallback is active, switching back once it answers, and
+/// if `health_check` is set every peer is checked that often, by default every
+/// `DEFAULT_HEALTH_CHECK` for balanced bridges.
+///
+/// Does nothing for bridges without fallbacks.
+pub fn watch(
+    peers: &Peers,
+    profile: &str,
+    failback: Option<Duration>,
+    health_check: Option<Duration>,
+) {
+    if !peers.has_fallbacks() {
+        return;
+    }
+
+    let mut tasks = vec![];
+    if let Some(interval) = failback.filter(|_| !peers.is_balanced()) {
+        tasks.push(tokio::spawn(probe_primary(
+            peers.clone(),
+            profile.to_string(),
+            interval,
+        )));
+    }
+    let health_check = health_check.or(peers.is_balanced().then_some(DEFAULT_HEALTH_CHECK));
+    if let Some(interval) = health_check {
+        tasks.push(tokio::spawn(check_health(
+            peers.clone(),
+            profile.to_string(),
+            interval,
+        )));
+    }
+
+    let previous = WATCHED
+        .lock()
+        .expect("Unable to unlock failover bridges")
+        .insert(
+            peers.url.clone(),
+            Watched {
+                peers: peers.clone(),
+                tasks,
+            },
+        );
+    for task in previous.into_iter().flat_map(|w| w.tasks) {
+        task.abort();
+    }
+}
+
+/// Stops the failback and health check tasks of the bridge of `url`, called when it stops.
+pub fn unwatch(url: &str) {
+    let watched = WATCHED
+        .lock()
+        .expect("Unable to unlock failover bridges")
+        .remove(url);
+    for task in watched.into_iter().flat_map(|w| w.tasks) {
+        task.abort();
+    }
+}
+
+/// The active peer and unhealthy peers of every running bridge with fallbacks.
+pub fn current() -> Vec<ActivePeer> {
+    WATCHED
+        .lock()
+        .expect("Unable to unlock failover bridges")
+        .values()
+        .map(|w| w.peers.status())
+        .collect()
+}
+
+async fn probe_primary(peers: Peers, profile: String, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        if peers.active.load(Ordering::SeqCst) == 0 {
+            continue;
+        }
+
+        let primary = &peers.id52s[0];
+        let reachability =
+            crate::probe::dial(primary, &profile, crate::probe::PREFLIGHT_TIMEOUT).await;
+        if reachability.reachable {
+            tracing::info!(url = peers.url, peer = primary, "primary peer is back");
+            peers.set_healthy(0, true);
+            peers.set_active(0);
+        }
+    }
+}
+
+async fn check_health(peers: Peers, profile: String, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        for (index, id52) in peers.id52s.iter().enumerate() {
+            let reachability =
+                crate::probe::dial(id52, &profile, crate::probe::PREFLIGHT_TIMEOUT).await;
+            peers.set_healthy(index, reachability.reachable);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peers(strategy: Strategy) -> Peers {
+        let id52s = ["a", "b", "c"].map(str::to_string).to_vec();
+        Peers::new("kulfi://a", id52s, strategy)
+    }
+
+    #[test]
+    fn failover_prefers_the_active_peer() {
+        let peers = peers(Strategy::Failover);
+        assert_eq!(peers.order(), [0, 1, 2]);
+        assert_eq!(peers.order(), [0, 1, 2]);
+
+        peers.set_active(2);
+        assert_eq!(peers.order(), [2, 0, 1]);
+        assert_eq!(peers.pick().0, "c");
+    }
+
+    #[test]
+    fn round_robin_rotates() {
+        let peers = peers(Strategy::RoundRobin);
+        let picked: Vec<String> = (0..4).map(|_| peers.pick().0).collect();
+        assert_eq!(picked, ["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn least_connections_counts_leases() {
+        let peers = peers(Strategy::LeastConnections);
+        let (first, _a) = peers.pick();
+        let (second, b) = peers.pick();
+        assert_eq!((first.as_str(), second.as_str()), ("a", "b"));
+        assert_eq!(peers.pick().0, "c");
+
+        drop(b);
+        assert_eq!(peers.order()[0], 1);
+    }
+
+    #[test]
+    fn skips_unhealthy_peers() {
+        for strategy in [
+            Strategy::Failover,
+            Strategy::RoundRobin,
+            Strategy::LeastConnections,
+        ] {
+            let peers = peers(strategy);
+            peers.set_healthy(0, false);
+            for _ in 0..3 {
+                let order = peers.order();
+                assert_eq!(order.len(), 3);
+                assert_eq!(order[2], 0, "{strategy:?}");
+                assert_ne!(peers.pick().0, "a", "{strategy:?}");
+            }
+            assert_eq!(peers.status().unhealthy, ["a"]);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_unhealthy_peers() {
+        let peers = peers(Strategy::RoundRobin);
+        peers.report("a", false);
+        peers.report("b", false);
+        peers.report("c", false);
+        let mut order = peers.order();
+        order.sort();
+        assert_eq!(order, [0, 1, 2]);
+
+        peers.report("b", true);
+        assert_eq!(peers.pick().0, "b");
+    }
+
+    #[tokio::test]
+    async fn dial_marks_failed_peers_unhealthy() {
+        let peers = peers(Strategy::RoundRobin);
+        let (peer, _lease) = peers
+            .dial(|id52| async move {
+                match id52.as_str() {
+                    "a" => Err(eyre::anyhow!("unreachable")),
+                    _ => Ok(id52),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(peer, "b");
+        assert_eq!(peers.status().unhealthy, ["a"]);
+
+        // the dead peer stays out of the rotation
+        for _ in 0..3 {
+            assert_ne!(peers.pick().0, "a");
+        }
+
+        // and rejoins once it answers
+        let _ = peers
+            .dial(|id52| async move {
+                match id52.as_str() {
+                    "a" => Ok(id52),
+                    _ => Err(eyre::anyhow!("unreachable")),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(peers.status().unhealthy, ["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn dial_fails_over_in_order() {
+        let peers = peers(Strategy::Failover);
+        let (peer, _lease) = peers
+            .dial(|id52| async move {
+                match id52.as_str() {
+                    "c" => Ok(id52),
+                    _ => Err(eyre::anyhow!("unreachable")),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(peer, "c");
+        assert_eq!(peers.active(), "c");
+        assert_eq!(peers.status().unhealthy, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn dial_tries_every_peer_when_all_are_unhealthy() {
+        let peers = peers(Strategy::Failover);
+        let mut tried = vec![];
+        let error = peers
+            .dial(|id52| {
+                tried.push(id52);
+                async { Err::<(), _>(eyre::anyhow!("unreachable")) }
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "unreachable");
+        assert_eq!(tried, ["a", "b", "c"]);
+        assert_eq!(peers.status().unhealthy, ["a", "b", "c"]);
+
+        // unhealthy peers are still tried as a last resort
+        let (peer, _lease) = peers
+            .dial(|id52| async move { Ok::<_, eyre::Error>(id52) })
+            .await
+            .unwrap();
+        assert_eq!(peer, "a");
+        assert_eq!(peers.status().unhealthy, ["b", "c"]);
+    }
+}
@@ -0,0 +1,768 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use std::collections::HashMap;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex, OnceLock, mpsc};
+
+/// Directory in the app data dir relative HAR file names are resolved in.
+const HAR_DIR: &str = "har";
+/// How much of each body a recording keeps, longer ones are cut and marked in a comment.
+pub const BODY_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Whether an HTTP bridge writes its exchanges to a HAR file or answers from one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Write every exchange to a new file, an existing one is never overwritten.
+    Record,
+    /// Answer every request from the file without contacting the peer.
+    Replay,
+}
+
+/// Which recorded request answers a replayed one, the method and path always have to match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Matching {
+    /// The query string has to be the same.
+    #[default]
+    Exact,
+    /// The query has to have the same parameters, in any order.
+    UnorderedQuery,
+    /// The query is not compared.
+    IgnoreQuery,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HarOptions {
+    pub mode: Mode,
+    /// Path of the HAR file, relative ones are in the app data dir. Derived from the bridge's
+    /// URL if unset.
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub matching: Matching,
+}
+
+/// HAR 1.2 as far as recording and replaying need it, other fields of files from browsers are
+/// ignored.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Har {
+    log: Log,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Log {
+    version: String,
+    creator: Creator,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Creator {
+    name: String,
+    version: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: String,
+    time: f64,
+    request: Request,
+    response: Response,
+    #[serde(default)]
+    cache: serde_json::Value,
+    #[serde(default)]
+    timings: serde_json::Value,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Request {
+    method: String,
+    url: String,
+    http_version: String,
+    #[serde(default)]
+    cookies: Vec<serde_json::Value>,
+    headers: Vec<NameValue>,
+    #[serde(default)]
+    query_string: Vec<NameValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    status: u16,
+    #[serde(default)]
+    status_text: String,
+    http_version: String,
+    #[serde(default)]
+    cookies: Vec<serde_json::Value>,
+    headers: Vec<NameValue>,
+    content: Content,
+    #[serde(default, rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct NameValue {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    mime_type: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    size: i64,
+    #[serde(default)]
+    mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+enum Session {
+    /// Hands the finished exchanges to the task writing the file.
+    Record(mpsc::Sender<Entry>),
+    Replay(Replay),
+}
+
+struct Replay {
+    matching: Matching,
+    har: Har,
+    /// How many times each entry was answered with, so requests recorded several times are
+    /// answered in the recorded order.
+    served: HashMap<usize, usize>,
+}
+
+/// The HAR sessions of running bridges, by URL.
+static SESSIONS: LazyLock<Mutex<HashMap<String, Session>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Remembers where relative HAR files go, called once from `setup`.
+pub fn init(app_handle: &tauri::AppHandle) -> eyre::Result<()> {
+    use tauri::Manager;
+
+    let dir = app_handle.path().app_data_dir()?.join(HAR_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let _ = DIR.set(dir);
+    Ok(())
+}
+
+/// Starts recording the bridge of `url` to a new HAR file or loads the file it replays.
+/// Recording fails if the file already exists.
+pub fn start(url: &str, options: &HarOptions) -> eyre::Result<()> {
+    let path = path(url, options.file.as_deref())?;
+    let session = match options.mode {
+        Mode::Record => {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::AlreadyExists => eyre::anyhow!(
+                        "{} already exists, pick another file or delete it",
+                        path.display()
+                    ),
+                    _ => eyre::Report::new(e),
+                })?;
+            let mut writer = Writer::new(file)?;
+            let (tx, rx) = mpsc::channel();
+            let url = url.to_string();
+            tokio::task::spawn_blocking(move || {
+                // ends once the session is stopped and its sender dropped
+                for entry in rx {
+                    if let Err(e) = writer.append(&entry) {
+                        tracing::error!(url, "failed to write HAR file: {e:?}");
+                    }
+                }
+            });
+            tracing::info!(url, path = %path.display(), "recording HAR");
+            Session::Record(tx)
+        }
+        Mode::Replay => {
+            let har: Har = serde_json::from_slice(&std::fs::read(&path)?)?;
+            tracing::info!(url, entries = har.log.entries.len(), path = %path.display(), "replaying HAR");
+            Session::Replay(Replay {
+                matching: options.matching,
+                har,
+                served: HashMap::new(),
+            })
+        }
+    };
+
+    SESSIONS
+        .lock()
+        .expect("Unable to unlock HAR sessions")
+        .insert(url.to_string(), session);
+    Ok(())
+}
+
+pub fn stop(url: &str) {
+    SESSIONS
+        .lock()
+        .expect("Unable to unlock HAR sessions")
+        .remove(url);
+}
+
+pub fn is_replaying(url: &str) -> bool {
+    matches!(
+        SESSIONS
+            .lock()
+            .expect("Unable to unlock HAR sessions")
+            .get(url),
+        Some(Session::Replay(_))
+    )
+}
+
+/// Appends a finished exchange to the HAR file of its bridge if it is recording, the file is
+/// written by a task of its own.
+pub fn record(exchange: &crate::inspect::Exchange) {
+    let recording = match SESSIONS
+        .lock()
+        .expect("Unable to unlock HAR sessions")
+        .get(&exchange.url)
+    {
+        Some(Session::Record(tx)) => tx.clone(),
+        _ => return,
+    };
+
+    if recording.send(entry(exchange)).is_err() {
+        tracing::error!(url = exchange.url, "HAR writer stopped");
+    }
+}
+
+/// The recorded response to `r` for a bridge replaying a HAR file, a 404 if nothing was
+/// recorded for it. `None` if the bridge is not replaying.
+pub fn respond<B>(
+    url: &str,
+    r: &hyper::Request<B>,
+) -> Option<hyper::Response<BoxBody<Bytes, eyre::Error>>> {
+    let mut sessions = SESSIONS.lock().expect("Unable to unlock HAR sessions");
+    let Some(Session::Replay(session)) = sessions.get_mut(url) else {
+        return None;
+    };
+
+    let matching: Vec<usize> = session
+        .har
+        .log
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| matches(session.matching, &entry.request, r))
+        .map(|(i, _)| i)
+        .collect();
+    let Some(&first) = matching.first() else {
+        tracing::info!(url, method = %r.method(), uri = %r.uri(), "no recorded response");
+        return Some(plain(
+            hyper::StatusCode::NOT_FOUND,
+            format!("malai: no recorded response for {} {}", r.method(), r.uri()),
+        ));
+    };
+
+    // repeated requests walk through the recorded responses, then stay on the last one
+    let served = session.served.entry(first).or_default();
+    let i = matching[(*served).min(matching.len() - 1)];
+    *served += 1;
+
+    match response(&session.har.log.entries[i].response) {
+        Ok(response) => Some(response),
+        Err(e) => Some(plain(
+            hyper::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("malai: broken recorded response: {e}"),
+        )),
+    }
+}
+
+fn path(url: &str, file: Option<&str>) -> eyre::Result<PathBuf> {
+    let dir = DIR
+        .get()
+        .ok_or_else(|| eyre::anyhow!("HAR files are not initialized"))?;
+    Ok(match file.filter(|file| !file.trim().is_empty()) {
+        // joining an absolute path keeps it as is
+        Some(file) => dir.join(file),
+        None => {
+            let name: String = url
+                .trim_start_matches("kulfi://")
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            dir.join(format!("{name}.har"))
+        }
+    })
+}
+
+/// Appends entries to a recording, each one written over the closing brackets of the file and
+/// followed by them again, so the file is valid HAR after every entry.
+struct Writer {
+    file: std::fs::File,
+    entries: usize,
+}
+
+/// What follows the entries of a HAR file serialized by serde, `entries` is the last field.
+const CLOSING: &[u8] = b"]}}";
+
+impl Writer {
+    fn new(mut file: std::fs::File) -> eyre::Result<Self> {
+        let har = Har {
+            log: Log {
+                version: "1.2".to_string(),
+                creator: Creator {
+                    name: "malai-client".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                entries: vec![],
+            },
+        };
+        file.write_all(&serde_json::to_vec(&har)?)?;
+        Ok(Writer { file, entries: 0 })
+    }
+
+    fn append(&mut self, entry: &Entry) -> eyre::Result<()> {
+        let mut json = if self.entries == 0 {
+            vec![]
+        } else {
+            vec![b',']
+        };
+        serde_json::to_writer(&mut json, entry)?;
+        json.extend_from_slice(CLOSING);
+
+        self.file.seek(SeekFrom::End(-(CLOSING.len() as i64)))?;
+        self.file.write_all(&json)?;
+        self.entries += 1;
+        Ok(())
+    }
+}
+
+fn matches<B>(matching: Matching, recorded: &Request, r: &hyper::Request<B>) -> bool {
+    let Ok(recorded_url) = url::Url::parse(&recorded.url) else {
+        return false;
+    };
+    if !recorded.method.eq_ignore_ascii_case(r.method().as_str())
+        || recorded_url.path() != r.uri().path()
+    {
+        return false;
+    }
+
+    match matching {
+        Matching::Exact => {
+            recorded_url.query().unwrap_or_default() == r.uri().query().unwrap_or_default()
+        }
+        Matching::UnorderedQuery => {
+            let sorted = |query: &str| {
+                let mut pairs: Vec<(String, String)> =
+                    url::form_urlencoded::parse(query.as_bytes())
+                        .into_owned()
+                        .collect();
+                pairs.sort();
+                pairs
+            };
+            sorted(recorded_url.query().unwrap_or_default())
+                == sorted(r.uri().query().unwrap_or_default())
+        }
+        Matching::IgnoreQuery => true,
+    }
+}
+
+fn entry(exchange: &crate::inspect::Exchange) -> Entry {
+    let request = &exchange.request;
+    let host = request
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("host"))
+        .map_or("127.0.0.1", |(_, value)| value.as_str());
+    let url = if exchange.uri.starts_with('/') {
+        format!("http://{host}{}", exchange.uri)
+    } else {
+        exchange.uri.clone()
+    };
+    let query_string = url::Url::parse(&url)
+        .map(|url| {
+            url.query_pairs()
+                .map(|(name, value)| NameValue {
+                    name: name.into_owned(),
+                    value: value.into_owned(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let post_data = (request.body_len > 0).then(|| {
+        let (text, _) = body_text(request.raw());
+        PostData {
+            mime_type: header(&request.headers, "content-type"),
+            text,
+            comment: request.truncated.then(|| truncated(request.body_len)),
+        }
+    });
+
+    let response = exchange.response.as_ref();
+    let content = response.map_or(
+        Content {
+            size: 0,
+            mime_type: String::new(),
+            text: None,
+            encoding: None,
+            comment: exchange.error.clone(),
+        },
+        |response| {
+            let (text, encoding) = body_text(response.raw());
+            Content {
+                size: response.body_len as i64,
+                mime_type: header(&response.headers, "content-type"),
+                text: Some(text),
+                encoding,
+                comment: response.truncated.then(|| truncated(response.body_len)),
+            }
+        },
+    );
+    let time = exchange.duration_ms.unwrap_or_default() as f64;
+
+    Entry {
+        started_date_time: crate::utc::Utc::from_unix_ms(exchange.time).iso8601(),
+        time,
+        request: Request {
+            method: exchange.method.clone(),
+            url,
+            http_version: exchange.version.clone(),
+            cookies: vec![],
+            headers: name_values(&request.headers),
+            query_string,
+            post_data,
+            headers_size: -1,
+            body_size: request.body_len as i64,
+        },
+        response: Response {
+            status: exchange.status.unwrap_or_default(),
+            status_text: exchange
+                .status
+                .and_then(|status| hyper::StatusCode::from_u16(status).ok())
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or_default()
+                .to_string(),
+            http_version: exchange.version.clone(),
+            cookies: vec![],
+            headers: response
+                .map(|r| name_values(&r.headers))
+                .unwrap_or_default(),
+            content,
+            redirect_url: response
+                .map(|r| header(&r.headers, "location"))
+                .unwrap_or_default(),
+            headers_size: -1,
+            body_size: response.map_or(-1, |r| r.body_len as i64),
+        },
+        cache: serde_json::json!({}),
+        timings: serde_json::json!({ "send": 0, "wait": time, "receive": 0 }),
+    }
+}
+
+fn response(recorded: &Response) -> eyre::Result<hyper::Response<BoxBody<Bytes, eyre::Error>>> {
+    let body = match (&recorded.content.text, recorded.content.encoding.as_deref()) {
+        (Some(text), Some("base64")) => data_encoding::BASE64.decode(text.as_bytes())?,
+        (Some(text), _) => text.clone().into_bytes(),
+        (None, _) => vec![],
+    };
+
+    let mut builder = hyper::Response::builder().status(recorded.status);
+    for NameValue { name, value } in &recorded.headers {
+        // HAR keeps the body decoded and it is sent whole, whatever encoding and framing it was
+        // recorded with
+        if [
+            "content-encoding",
+            "content-length",
+            "transfer-encoding",
+            "connection",
+        ]
+        .iter()
+        .any(|skip| name.eq_ignore_ascii_case(skip))
+        {
+            continue;
+        }
+        builder = builder.header(name, value);
+    }
+    Ok(builder.body(
+        Full::new(Bytes::from(body))
+            .map_err(|never| match never {})
+            .boxed(),
+    )?)
+}
+
+fn plain(
+    status: hyper::StatusCode,
+    message: String,
+) -> hyper::Response<BoxBody<Bytes, eyre::Error>> {
+    let mut response = hyper::Response::new(
+        Full::new(Bytes::from(message))
+            .map_err(|never| match never {})
+            .boxed(),
+    );
+    *response.status_mut() = status;
+    response
+}
+
+/// The body as text, base64 encoded if it is not UTF-8.
+fn body_text(raw: &[u8]) -> (String, Option<String>) {
+    match std::str::from_utf8(raw) {
+        Ok(text) => (text.to_string(), None),
+        Err(_) => (
+            data_encoding::BASE64.encode(raw),
+            Some("base64".to_string()),
+        ),
+    }
+}
+
+fn header(headers: &[(String, String)], name: &str) -> String {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default()
+}
+
+fn name_values(headers: &[(String, String)]) -> Vec<NameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| NameValue {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn truncated(body_len: u64) -> String {
+    format!("truncated to {BODY_LIMIT} of {body_len} bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(method: &str, url: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            url: url.to_string(),
+            http_version: "HTTP/1.1".to_string(),
+            cookies: vec![],
+            headers: vec![],
+            query_string: vec![],
+            post_data: None,
+            headers_size: -1,
+            body_size: 0,
+        }
+    }
+
+    fn header_names(response: &hyper::Response<BoxBody<Bytes, eyre::Error>>) -> Vec<&str> {
+        response
+            .headers()
+            .keys()
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    async fn body(response: hyper::Response<BoxBody<Bytes, eyre::Error>>) -> Bytes {
+        response.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    fn request(method: &str, uri: &str) -> hyper::Request<()> {
+        hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn method_and_path_always_have_to_match() {
+        let recorded = recorded("GET", "http://127.0.0.1:8080/api/items?page=1");
+        for matching in [
+            Matching::Exact,
+            Matching::UnorderedQuery,
+            Matching::IgnoreQuery,
+        ] {
+            assert!(matches(
+                matching,
+                &recorded,
+                &request("get", "/api/items?page=1")
+            ));
+            assert!(!matches(
+                matching,
+                &recorded,
+                &request("POST", "/api/items?page=1")
+            ));
+            assert!(!matches(
+                matching,
+                &recorded,
+                &request("GET", "/api/item?page=1")
+            ));
+        }
+    }
+
+    #[test]
+    fn exact_compares_the_query_as_is() {
+        let recorded = recorded("GET", "http://127.0.0.1:8080/search?q=a&page=2");
+        assert!(matches(
+            Matching::Exact,
+            &recorded,
+            &request("GET", "/search?q=a&page=2")
+        ));
+        assert!(!matches(
+            Matching::Exact,
+            &recorded,
+            &request("GET", "/search?page=2&q=a")
+        ));
+        assert!(!matches(
+            Matching::Exact,
+            &recorded,
+            &request("GET", "/search")
+        ));
+    }
+
+    #[test]
+    fn unordered_query_compares_the_parameters() {
+        let recorded = recorded("GET", "http://127.0.0.1:8080/search?q=a&page=2");
+        let matching = Matching::UnorderedQuery;
+        assert!(matches(
+            matching,
+            &recorded,
+            &request("GET", "/search?page=2&q=a")
+        ));
+        assert!(!matches(
+            matching,
+            &recorded,
+            &request("GET", "/search?page=3&q=a")
+        ));
+        assert!(!matches(
+            matching,
+            &recorded,
+            &request("GET", "/search?q=a")
+        ));
+    }
+
+    #[test]
+    fn ignore_query_matches_any_query() {
+        let recorded = recorded("GET", "http://127.0.0.1:8080/search?q=a");
+        let matching = Matching::IgnoreQuery;
+        assert!(matches(matching, &recorded, &request("GET", "/search?q=b")));
+        assert!(matches(matching, &recorded, &request("GET", "/search")));
+    }
+
+    #[test]
+    fn writer_keeps_the_file_valid() {
+        let path = std::env::temp_dir().join(format!("malai-har-{}.har", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer = Writer::new(file).unwrap();
+        let read = || -> Har { serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap() };
+        assert!(read().log.entries.is_empty());
+
+        let entry = |url: &str| Entry {
+            started_date_time: "2025-01-01T00:00:00.000Z".to_string(),
+            time: 1.0,
+            request: recorded("GET", url),
+            response: Response {
+                status: 200,
+                status_text: "OK".to_string(),
+                http_version: "HTTP/1.1".to_string(),
+                cookies: vec![],
+                headers: vec![],
+                content: Content {
+                    size: 0,
+                    mime_type: String::new(),
+                    text: None,
+                    encoding: None,
+                    comment: None,
+                },
+                redirect_url: String::new(),
+                headers_size: -1,
+                body_size: 0,
+            },
+            cache: serde_json::json!({}),
+            timings: serde_json::json!({}),
+        };
+        writer.append(&entry("http://127.0.0.1/a")).unwrap();
+        writer.append(&entry("http://127.0.0.1/b")).unwrap();
+
+        let har = read();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(har.log.version, "1.2");
+        let urls: Vec<&str> = har
+            .log
+            .entries
+            .iter()
+            .map(|entry| entry.request.url.as_str())
+            .collect();
+        assert_eq!(urls, vec!["http://127.0.0.1/a", "http://127.0.0.1/b"]);
+    }
+
+    #[tokio::test]
+    async fn replays_decoded_bodies_without_their_encoding() {
+        // as browsers export it: the body decoded, the headers as they came over the wire
+        let recorded: Response = serde_json::from_value(serde_json::json!({
+            "status": 200,
+            "statusText": "OK",
+            "httpVersion": "HTTP/2",
+            "headers": [
+                { "name": "content-type", "value": "application/json" },
+                { "name": "content-encoding", "value": "gzip" },
+                { "name": "content-length", "value": "31" },
+                { "name": "transfer-encoding", "value": "chunked" }
+            ],
+            "content": { "size": 15, "mimeType": "application/json", "text": "{\"items\":[1,2]}" },
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": 31
+        }))
+        .unwrap();
+
+        let response = response(&recorded).unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(header_names(&response), vec!["content-type"]);
+        assert_eq!(body(response).await, "{\"items\":[1,2]}");
+    }
+
+    #[tokio::test]
+    async fn replays_base64_bodies() {
+        let recorded: Response = serde_json::from_value(serde_json::json!({
+            "status": 200,
+            "httpVersion": "HTTP/1.1",
+            "headers": [
+                { "name": "Content-Type", "value": "image/png" },
+                { "name": "Content-Encoding", "value": "br" }
+            ],
+            "content": { "size": 4, "mimeType": "image/png", "text": "iVBORw==", "encoding": "base64" },
+            "headersSize": -1,
+            "bodySize": 4
+        }))
+        .unwrap();
+
+        let response = response(&recorded).unwrap();
+        assert_eq!(header_names(&response), vec!["content-type"]);
+        assert_eq!(body(response).await, &b"\x89PNG"[..]);
+    }
+}
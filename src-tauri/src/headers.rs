@@ -0,0 +1,516 @@
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// Maps the origins a peer's web app thinks it is served from to the bridge, so its redirects
+/// and cookies keep working when browsed through `127.0.0.1`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ResponseRewrite {
+    /// Like `https://app.example.com`, or a bare host name to match it with any scheme and port.
+    pub origins: Vec<String>,
+}
+
+/// Changes to the requests an HTTP bridge forwards, e.g. for peers serving name-based virtual
+/// hosts or expecting an auth token.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RequestHeaders {
+    /// Sent as `Host` instead of the `127.0.0.1:{port}` the browser addressed.
+    pub host: Option<String>,
+    /// Added to every request, replacing the values the browser sent.
+    pub set: Vec<Header>,
+    /// Names of headers that are not forwarded.
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+/// `RequestHeaders` with the names and values validated.
+#[derive(Debug, Clone, Default)]
+struct RequestRules {
+    host: Option<HeaderValue>,
+    set: Vec<(HeaderName, HeaderValue)>,
+    remove: Vec<HeaderName>,
+}
+
+/// An origin of `ResponseRewrite`, unset parts match anything.
+#[derive(Debug, Clone)]
+struct Origin {
+    scheme: Option<String>,
+    host: String,
+    port: Option<u16>,
+}
+
+/// The parsed origins of every HTTP bridge rewriting responses, by URL.
+static REWRITES: LazyLock<Mutex<HashMap<String, Vec<Origin>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The request rules of every HTTP bridge changing its requests, by URL.
+static REQUEST_RULES: LazyLock<Mutex<HashMap<String, RequestRules>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Starts changing the requests of the bridge of `url`, or stops if `headers` changes nothing.
+/// Fails on an invalid name, value or host, values are left out of the error as they may be
+/// secrets.
+pub fn set_request_headers(url: &str, headers: Option<RequestHeaders>) -> eyre::Result<()> {
+    let headers = headers.unwrap_or_default();
+    let name = |name: &str| {
+        HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| eyre::anyhow!("invalid header name {name:?}"))
+    };
+
+    let rules = RequestRules {
+        host: headers
+            .host
+            .filter(|host| !host.trim().is_empty())
+            .map(|host| {
+                HeaderValue::from_str(host.trim())
+                    .map_err(|_| eyre::anyhow!("invalid host {host:?}"))
+            })
+            .transpose()?,
+        set: headers
+            .set
+            .iter()
+            .map(|header| -> eyre::Result<(HeaderName, HeaderValue)> {
+                let value = HeaderValue::from_str(header.value.trim())
+                    .map_err(|_| eyre::anyhow!("invalid value for header {}", header.name))?;
+                Ok((name(&header.name)?, value))
+            })
+            .collect::<eyre::Result<_>>()?,
+        remove: headers
+            .remove
+            .iter()
+            .map(|header| name(header))
+            .collect::<eyre::Result<_>>()?,
+    };
+
+    let mut request_rules = REQUEST_RULES
+        .lock()
+        .expect("Unable to unlock request rules");
+    if rules.host.is_none() && rules.set.is_empty() && rules.remove.is_empty() {
+        request_rules.remove(url);
+    } else {
+        request_rules.insert(url.to_string(), rules);
+    }
+    Ok(())
+}
+
+/// Applies the request rules of the bridge of `url` to a request about to be sent to the peer:
+/// removes headers first, then overrides `Host` and sets the extra headers.
+pub fn rewrite_request(url: &str, headers: &mut HeaderMap) {
+    let Some(rules) = REQUEST_RULES
+        .lock()
+        .expect("Unable to unlock request rules")
+        .get(url)
+        .cloned()
+    else {
+        return;
+    };
+
+    for name in &rules.remove {
+        headers.remove(name);
+    }
+    if let Some(host) = rules.host {
+        headers.insert(header::HOST, host);
+    }
+    for (name, value) in rules.set {
+        headers.insert(name, value);
+    }
+}
+
+/// Starts rewriting the responses of the bridge of `url`, or stops if there are no origins.
+///
+/// `host` is the `Host` the bridge sends instead of the browser's, the peer's web app sees it
+/// as its own origin too. Fails on an invalid origin.
+pub fn set_rewrite(
+    url: &str,
+    rewrite: Option<ResponseRewrite>,
+    host: Option<&str>,
+) -> eyre::Result<()> {
+    let mut origins = rewrite
+        .unwrap_or_default()
+        .origins
+        .iter()
+        .map(|origin| {
+            Origin::parse(origin).ok_or_else(|| eyre::anyhow!("invalid origin {origin:?}"))
+        })
+        .collect::<eyre::Result<Vec<Origin>>>()?;
+    if let Some(host) = host.filter(|host| !host.trim().is_empty()) {
+        origins
+            .push(Origin::from_host(host).ok_or_else(|| eyre::anyhow!("invalid host {host:?}"))?);
+    }
+
+    let mut rewrites = REWRITES.lock().expect("Unable to unlock rewrites");
+    if origins.is_empty() {
+        rewrites.remove(url);
+    } else {
+        rewrites.insert(url.to_string(), origins);
+    }
+    Ok(())
+}
+
+pub fn forget(url: &str) {
+    REWRITES
+        .lock()
+        .expect("Unable to unlock rewrites")
+        .remove(url);
+    REQUEST_RULES
+        .lock()
+        .expect("Unable to unlock request rules")
+        .remove(url);
+}
+
+/// Points `Location`, `Content-Location` and `Refresh` URLs on one of the peer's origins at
+/// `http://{host}`, the bridge as the browser addressed it, and drops cookie `Domain` attributes
+/// scoped to them so the cookies are kept for the bridge.
+pub fn rewrite_response(url: &str, host: &str, headers: &mut HeaderMap) {
+    let Some(origins) = REWRITES
+        .lock()
+        .expect("Unable to unlock rewrites")
+        .get(url)
+        .cloned()
+    else {
+        return;
+    };
+
+    let local = format!("http://{host}");
+    for name in [header::LOCATION, header::CONTENT_LOCATION] {
+        rewrite_values(headers, name, |value| rewrite_url(&origins, &local, value));
+    }
+    rewrite_values(headers, header::REFRESH, |value| {
+        rewrite_refresh(&origins, &local, value)
+    });
+    rewrite_values(headers, header::SET_COOKIE, |value| {
+        rewrite_cookie(&origins, value)
+    });
+}
+
+/// Replaces every value of `name` that `rewrite` changes, keeping their order.
+fn rewrite_values(
+    headers: &mut HeaderMap,
+    name: HeaderName,
+    rewrite: impl Fn(&str) -> Option<String>,
+) {
+    let values: Vec<HeaderValue> = headers.get_all(&name).iter().cloned().collect();
+    let rewritten: Vec<Option<String>> = values
+        .iter()
+        .map(|value| value.to_str().ok().and_then(&rewrite))
+        .collect();
+    if rewritten.iter().all(Option::is_none) {
+        return;
+    }
+
+    headers.remove(&name);
+    for (value, rewritten) in values.into_iter().zip(rewritten) {
+        let value = rewritten
+            .and_then(|rewritten| HeaderValue::from_str(&rewritten).ok())
+            .unwrap_or(value);
+        headers.append(name.clone(), value);
+    }
+}
+
+fn rewrite_url(origins: &[Origin], local: &str, value: &str) -> Option<String> {
+    let parsed = url::Url::parse(value.trim()).ok()?;
+    if !origins.iter().any(|origin| origin.matches(&parsed)) {
+        return None;
+    }
+
+    let mut rewritten = format!("{local}{}", parsed.path());
+    if let Some(query) = parsed.query() {
+        rewritten.push('?');
+        rewritten.push_str(query);
+    }
+    if let Some(fragment) = parsed.fragment() {
+        rewritten.push('#');
+        rewritten.push_str(fragment);
+    }
+    Some(rewritten)
+}
+
+/// `Refresh: 5; url=https://...`
+fn rewrite_refresh(origins: &[Origin], local: &str, value: &str) -> Option<String> {
+    let start = value.to_ascii_lowercase().find("url=")? + "url=".len();
+    let (delay, target) = value.split_at(start);
+    let target = target.trim().trim_matches(|c| c == '"' || c == '\'');
+    Some(format!("{delay}{}", rewrite_url(origins, local, target)?))
+}
+
+fn rewrite_cookie(origins: &[Origin], value: &str) -> Option<String> {
+    let mut parts = value.split(';');
+    let mut rewritten = parts.next()?.to_string();
+    let mut changed = false;
+    for attribute in parts {
+        let scoped_to_peer = attribute
+            .trim()
+            .split_once('=')
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("domain"))
+            .is_some_and(|(_, domain)| {
+                let domain = domain.trim().trim_start_matches('.');
+                origins.iter().any(|origin| origin.in_domain(domain))
+            });
+        if scoped_to_peer {
+            changed = true;
+        } else {
+            rewritten.push(';');
+            rewritten.push_str(attribute);
+        }
+    }
+    changed.then_some(rewritten)
+}
+
+impl Origin {
+    fn parse(origin: &str) -> Option<Self> {
+        let origin = origin.trim();
+        if !origin.contains("://") {
+            return Origin::from_host(origin);
+        }
+
+        let parsed = url::Url::parse(origin).ok()?;
+        Some(Origin {
+            scheme: Some(parsed.scheme().to_string()),
+            host: parsed.host_str()?.to_ascii_lowercase(),
+            port: parsed.port_or_known_default(),
+        })
+    }
+
+    /// A host name with an optional port, like a `Host` header, matching any scheme.
+    fn from_host(host: &str) -> Option<Self> {
+        let host = host.trim();
+        if host.is_empty() || host.contains(['/', '?', '#', '@']) {
+            return None;
+        }
+
+        let parsed = url::Url::parse(&format!("http://{host}")).ok()?;
+        Some(Origin {
+            scheme: None,
+            host: parsed.host_str()?.to_ascii_lowercase(),
+            port: parsed.port(),
+        })
+    }
+
+    fn matches(&self, url: &url::Url) -> bool {
+        url.host_str()
+            .is_some_and(|host| host.eq_ignore_ascii_case(&self.host))
+            && self.scheme.as_deref().is_none_or(|s| s == url.scheme())
+            && self
+                .port
+                .is_none_or(|p| Some(p) == url.port_or_known_default())
+    }
+
+    /// Whether a cookie for `domain` would be sent to this origin.
+    fn in_domain(&self, domain: &str) -> bool {
+        let domain = domain.to_ascii_lowercase();
+        self.host == domain || self.host.ends_with(&format!(".{domain}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: &str = "http://127.0.0.1:8080";
+
+    fn origins(origins: &[&str]) -> Vec<Origin> {
+        origins
+            .iter()
+            .map(|origin| Origin::parse(origin).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parses_origins() {
+        assert!(Origin::parse("").is_none());
+        assert!(Origin::parse("https://").is_none());
+        assert!(Origin::parse("app.example.com/path").is_none());
+
+        let origin = Origin::parse("https://App.Example.com").unwrap();
+        assert_eq!(origin.scheme.as_deref(), Some("https"));
+        assert_eq!(origin.host, "app.example.com");
+        assert_eq!(origin.port, Some(443));
+
+        let origin = Origin::parse("app.example.com:8443").unwrap();
+        assert_eq!(origin.scheme, None);
+        assert_eq!(origin.host, "app.example.com");
+        assert_eq!(origin.port, Some(8443));
+    }
+
+    #[test]
+    fn rewrites_urls_on_the_origins() {
+        let origins = origins(&["https://app.example.com"]);
+        assert_eq!(
+            rewrite_url(
+                &origins,
+                LOCAL,
+                "https://app.example.com/login?next=%2F#top"
+            )
+            .as_deref(),
+            Some("http://127.0.0.1:8080/login?next=%2F#top")
+        );
+        assert_eq!(
+            rewrite_url(&origins, LOCAL, "https://APP.example.com:443/").as_deref(),
+            Some("http://127.0.0.1:8080/")
+        );
+        // other scheme, port or host, and relative URLs are left alone
+        assert_eq!(
+            rewrite_url(&origins, LOCAL, "http://app.example.com/"),
+            None
+        );
+        assert_eq!(
+            rewrite_url(&origins, LOCAL, "https://app.example.com:8443/"),
+            None
+        );
+        assert_eq!(
+            rewrite_url(&origins, LOCAL, "https://other.example.com/"),
+            None
+        );
+        assert_eq!(rewrite_url(&origins, LOCAL, "/login"), None);
+    }
+
+    #[test]
+    fn bare_hosts_match_any_scheme_and_port() {
+        let origins = origins(&["app.example.com"]);
+        for url in ["http://app.example.com/a", "https://app.example.com:8443/a"] {
+            assert_eq!(
+                rewrite_url(&origins, LOCAL, url).as_deref(),
+                Some("http://127.0.0.1:8080/a")
+            );
+        }
+    }
+
+    #[test]
+    fn rewrites_refresh_targets() {
+        let origins = origins(&["https://app.example.com"]);
+        assert_eq!(
+            rewrite_refresh(&origins, LOCAL, "5; URL='https://app.example.com/next'").as_deref(),
+            Some("5; URL=http://127.0.0.1:8080/next")
+        );
+        assert_eq!(rewrite_refresh(&origins, LOCAL, "5"), None);
+        assert_eq!(
+            rewrite_refresh(&origins, LOCAL, "0; url=https://other.example.com/"),
+            None
+        );
+    }
+
+    #[test]
+    fn drops_cookie_domains_of_the_origins() {
+        let origins = origins(&["https://app.example.com"]);
+        assert_eq!(
+            rewrite_cookie(&origins, "id=1; Path=/; Domain=.example.com; HttpOnly").as_deref(),
+            Some("id=1; Path=/; HttpOnly")
+        );
+        assert_eq!(
+            rewrite_cookie(&origins, "id=1; domain=app.example.com").as_deref(),
+            Some("id=1")
+        );
+        assert_eq!(rewrite_cookie(&origins, "id=1; Domain=other.com"), None);
+        assert_eq!(rewrite_cookie(&origins, "id=1; Path=/"), None);
+    }
+
+    #[test]
+    fn rewrites_response_headers_in_order() {
+        let url = "kulfi://rewrites-response-headers-in-order";
+        set_rewrite(
+            url,
+            Some(ResponseRewrite {
+                origins: vec!["https://app.example.com".to_string()],
+            }),
+            Some("internal.example.com"),
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::LOCATION,
+            HeaderValue::from_static("http://internal.example.com/home"),
+        );
+        headers.append(
+            header::SET_COOKIE,
+            HeaderValue::from_static("a=1; Domain=example.com"),
+        );
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("b=2"));
+        rewrite_response(url, "127.0.0.1:8080", &mut headers);
+        forget(url);
+
+        assert_eq!(headers[header::LOCATION], "http://127.0.0.1:8080/home");
+        let cookies: Vec<_> = headers.get_all(header::SET_COOKIE).iter().collect();
+        assert_eq!(cookies, vec!["a=1", "b=2"]);
+    }
+
+    #[test]
+    fn changes_request_headers() {
+        let url = "kulfi://changes-request-headers";
+        set_request_headers(
+            url,
+            Some(RequestHeaders {
+                host: Some(" app.example.com ".to_string()),
+                set: vec![Header {
+                    name: "Authorization".to_string(),
+                    value: " Bearer token".to_string(),
+                }],
+                remove: vec!["cookie".to_string()],
+            }),
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("127.0.0.1:8080"));
+        headers.insert(header::COOKIE, HeaderValue::from_static("id=1"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic x"));
+        rewrite_request(url, &mut headers);
+        forget(url);
+
+        assert_eq!(headers[header::HOST], "app.example.com");
+        assert_eq!(headers[header::AUTHORIZATION], "Bearer token");
+        assert!(!headers.contains_key(header::COOKIE));
+    }
+
+    #[test]
+    fn rejects_invalid_request_headers() {
+        let url = "kulfi://rejects-invalid-request-headers";
+        let set = |name: &str, value: &str| {
+            set_request_headers(
+                url,
+                Some(RequestHeaders {
+                    set: vec![Header {
+                        name: name.to_string(),
+                        value: value.to_string(),
+                    }],
+                    ..Default::default()
+                }),
+            )
+        };
+        assert!(set("X Token", "a").is_err());
+        let error = set("X-Token", "sec\nret").unwrap_err().to_string();
+        assert!(!error.contains("sec"), "{error}");
+        assert!(
+            set_request_headers(
+                url,
+                Some(RequestHeaders {
+                    host: Some("app\nexample.com".to_string()),
+                    ..Default::default()
+                }),
+            )
+            .is_err()
+        );
+        assert!(REQUEST_RULES.lock().unwrap().get(url).is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_origins() {
+        let url = "kulfi://rejects-invalid-origins";
+        let rewrite = |origin: &str| {
+            Some(ResponseRewrite {
+                origins: vec![origin.to_string()],
+            })
+        };
+        assert!(set_rewrite(url, rewrite("https://"), None).is_err());
+        assert!(set_rewrite(url, rewrite("app.example.com"), Some("a b")).is_err());
+        assert!(set_rewrite(url, rewrite("app.example.com"), Some(" ")).is_ok());
+        forget(url);
+    }
+}
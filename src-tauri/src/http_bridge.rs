@@ -1,13 +1,18 @@
 use eyre::WrapErr;
+use tracing::Instrument;
+
 #[tracing::instrument(skip_all)]
+#[allow(clippy::too_many_arguments)]
 pub async fn http_bridge(
     port: u16,
-    proxy_target: Option<String>,
+    proxy_target: Option<crate::failover::Peers>,
+    profile: String,
+    activity: crate::idle::Activity,
     graceful: kulfi_utils::Graceful,
     mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
     startup_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
     post_start: impl FnOnce(u16) -> eyre::Result<()>,
-) {
+) -> Result<(), String> {
     let listener = match tokio::net::TcpListener::bind(format!("127.0.0.1:{port}"))
         .await
         .wrap_err_with(|| {
@@ -16,9 +21,9 @@ pub async fn http_bridge(
         Ok(listener) => listener,
         Err(e) => {
             let error_msg = format!("Failed to bind HTTP to port {port}: {e}");
-            eprintln!("{error_msg}");
-            let _ = startup_tx.send(Err(error_msg));
-            return;
+            tracing::error!("{error_msg}");
+            let _ = startup_tx.send(Err(error_msg.clone()));
+            return Err(error_msg);
         }
     };
 
@@ -29,16 +34,17 @@ pub async fn http_bridge(
         Ok(_) => {}
         Err(e) => {
             let error_msg = format!("Failed to open browser: {e}");
-            eprintln!("{error_msg}");
-            let _ = startup_tx.send(Err(error_msg));
-            return;
+            tracing::error!("{error_msg}");
+            let _ = startup_tx.send(Err(error_msg.clone()));
+            return Err(error_msg);
         }
     }
 
-    println!("Listening on http://127.0.0.1:{port}");
+    tracing::info!("Listening on http://127.0.0.1:{port}");
     let _ = startup_tx.send(Ok(()));
 
-    let peer_connections = kulfi_utils::PeerStreamSenders::default();
+    let peer_connections = crate::pool::connections();
+    let mut acceptor = crate::accept::Acceptor::new(activity.url());
 
     loop {
         tokio::select! {
@@ -46,28 +52,63 @@ pub async fn http_bridge(
                 tracing::info!("shutting down");
                 break;
             },
-            r = listener.accept() => {
+            r = acceptor.accept(&listener) => {
                 match r {
-                    Ok((stream, _addr)) => {
+                    Ok((stream, client)) => {
                 tracing::info!("got connection");
                 let graceful_for_handle_connection = graceful.clone();
                 let peer_connections = peer_connections.clone();
                 let proxy_target = proxy_target.clone();
+                let profile = profile.clone();
+                let url = activity.url().to_string();
+                let open = activity.open();
                 graceful.spawn(async move {
-                    let self_endpoint = kulfi_utils::global_iroh_endpoint().await;
+                    let _open = open;
+                    let self_endpoint = match crate::endpoint::get(&profile).await {
+                        Ok(endpoint) => endpoint,
+                        Err(e) => {
+                            tracing::error!("failed to get endpoint: {e:?}");
+                            let summary = "The bridge could not set up its network endpoint.";
+                            serve_error(stream, url, client, None, summary, e).await;
+                            return;
+                        }
+                    };
+                    // balanced bridges pick a peer per request, the others per connection
+                    let (proxy_target, balanced) = match proxy_target {
+                        // replaying bridges answer from their HAR file and never reach a peer
+                        _ if crate::har::is_replaying(&url) => (None, None),
+                        Some(peers) if peers.is_balanced() => (None, Some(peers)),
+                        Some(peers) => {
+                            match select_peer(&peers, &self_endpoint, &peer_connections).await {
+                                Ok(peer) => (Some(peer), None),
+                                Err(e) => {
+                                    tracing::error!("no peer reachable: {e:?}");
+                                    let peer = Some(peers.active().to_string());
+                                    let summary = "No peer of this bridge could be reached.";
+                                    serve_error(stream, url, client, peer, summary, e).await;
+                                    return;
+                                }
+                            }
+                        }
+                        None => (None, None),
+                    };
                     handle_connection(
                         self_endpoint,
                         stream,
                         graceful_for_handle_connection,
                         peer_connections,
                         proxy_target,
+                        balanced,
+                        url,
+                        client,
                     )
                     .await
-                });
+                }
+                .in_current_span());
             }
             Err(e) => {
                 tracing::error!("failed to accept: {e:?}");
-                break;
+                return Err(format!("Failed to accept: {e}"));
             }
                 }
             }
@@ -96,20 +137,96 @@ pub async fn http_bridge(
         //     }
         // }
     }
+
+    Ok(())
+}
+
+/// Picks the peer a new connection is proxied to. With fallbacks the pooled connection to the
+/// active peer is pinged first, so a dead peer fails over before the request is consumed.
+async fn select_peer(
+    peers: &crate::failover::Peers,
+    self_endpoint: &iroh::Endpoint,
+    peer_connections: &kulfi_utils::PeerStreamSenders,
+) -> eyre::Result<String> {
+    if !peers.has_fallbacks() {
+        return Ok(peers.active().to_string());
+    }
+
+    let (peer, _lease) = peers
+        .dial(|id52| async move {
+            crate::probe::ping_over_stream(self_endpoint, &id52, peer_connections.clone()).await?;
+            Ok(id52)
+        })
+        .await?;
+    Ok(peer)
+}
+
+/// Answers the requests of a connection that can not be proxied with a 502 error page. The
+/// connection is closed after the first response, so a retry starts over with a new one.
+async fn serve_error(
+    stream: tokio::net::TcpStream,
+    url: String,
+    client: std::net::SocketAddr,
+    peer: Option<String>,
+    summary: &str,
+    error: eyre::Error,
+) {
+    let io = hyper_util::rt::TokioIo::new(stream);
+    let limits = crate::http_limits::get(&url);
+    let error = format!("{error:#}");
+
+    let mut builder =
+        hyper_util::server::conn::auto::Builder::new(hyper_util::rt::tokio::TokioExecutor::new());
+    builder
+        .http1()
+        .timer(hyper_util::rt::TokioTimer::new())
+        .header_read_timeout(limits.header_timeout());
+    let service = hyper::service::service_fn(|r| {
+        let mut access = access_entry(&r, url.clone(), client);
+        access.peer = peer.clone().unwrap_or_default();
+        let page = crate::error_page::ErrorPage {
+            status: hyper::StatusCode::BAD_GATEWAY,
+            peer: peer.clone(),
+            summary: summary.to_string(),
+            error: error.clone(),
+            retry: access.path.clone(),
+        };
+        let mut response = page.response();
+        response.headers_mut().insert(
+            hyper::header::CONNECTION,
+            hyper::header::HeaderValue::from_static("close"),
+        );
+        std::future::ready(Ok::<_, std::convert::Infallible>(
+            crate::access_log::record_response(access, response),
+        ))
+    });
+    if let Err(e) = builder.serve_connection(io, service).await {
+        tracing::error!("connection error: {e:?}");
+    }
 }
 
 #[tracing::instrument(skip_all)]
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_connection(
     self_endpoint: iroh::Endpoint,
     stream: tokio::net::TcpStream,
     graceful: kulfi_utils::Graceful,
     peer_connections: kulfi_utils::PeerStreamSenders,
     proxy_target: Option<String>,
+    balanced: Option<crate::failover::Peers>,
+    url: String,
+    client: std::net::SocketAddr,
 ) {
     let io = hyper_util::rt::TokioIo::new(stream);
+    let limits = crate::http_limits::get(&url);
+    let idle = crate::http_limits::ConnectionIdle::default();
 
-    let builder =
+    let mut builder =
         hyper_util::server::conn::auto::Builder::new(hyper_util::rt::tokio::TokioExecutor::new());
+    builder
+        .http1()
+        .timer(hyper_util::rt::TokioTimer::new())
+        .header_read_timeout(limits.header_timeout());
     // the following builder runs only http2 service, whereas the hyper_util auto Builder runs an
     // http1.1 server that upgrades to http2 if the client requests.
     // let builder = hyper::server::conn::http2::Builder::new(hyper_util::rt::tokio::TokioExecutor::new());
@@ -117,7 +234,14 @@ pub async fn handle_connection(
         let conn = builder
             .serve_connection(
                 io,
-                hyper::service::service_fn(|r| handle_request(r, self_endpoint.clone(), peer_connections.clone(), proxy_target.clone(), graceful.clone())),
+                hyper::service::service_fn(|r| {
+                    let busy = idle.busy();
+                    let response = handle_request(r, self_endpoint.clone(), peer_connections.clone(), proxy_target.clone(), balanced.clone(), graceful.clone(), url.clone(), client);
+                    async move {
+                        let _busy = busy;
+                        response.await
+                    }
+                }),
             );
     }
 
@@ -126,6 +250,12 @@ pub async fn handle_connection(
             conn.as_mut().graceful_shutdown();
             conn.await
         }
+        // responses still streaming are finished before the connection is closed
+        _ = idle.idled(limits.idle_timeout()) => {
+            tracing::info!("closing idle connection");
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
         r = &mut conn => r,
     } {
         tracing::error!("connection error2: {e:?}");
@@ -133,37 +263,351 @@ pub async fn handle_connection(
 }
 
 #[tracing::instrument(skip_all)]
+#[allow(clippy::too_many_arguments)]
 async fn handle_request(
     r: hyper::Request<hyper::body::Incoming>,
     self_endpoint: iroh::Endpoint,
     peer_connections: kulfi_utils::PeerStreamSenders,
     proxy_target: Option<String>,
+    balanced: Option<crate::failover::Peers>,
     graceful: kulfi_utils::Graceful,
+    url: String,
+    client: std::net::SocketAddr,
 ) -> kulfi_utils::http::ProxyResult<eyre::Error> {
-    let peer_id = match get_peer_id52_from_host(
-        r.headers().get("Host").and_then(|h| h.to_str().ok()),
+    if let Some(response) = crate::har::respond(&url, &r) {
+        let access = access_entry(&r, url, client);
+        return Ok(crate::access_log::record_response(access, response));
+    }
+
+    if crate::inspect::is_enabled(&url) && !crate::inspect::is_upgrade(&r) {
+        let bridge = url.clone();
+        let (_, response) = crate::inspect::capture(&bridge, None, r, move |exchange| {
+            move |r| {
+                proxy_request(
+                    r,
+                    self_endpoint.clone(),
+                    peer_connections.clone(),
+                    proxy_target.clone(),
+                    balanced.clone(),
+                    graceful.clone(),
+                    url.clone(),
+                    client,
+                    Some(exchange),
+                )
+            }
+        })
+        .await;
+        return Ok(response);
+    }
+
+    proxy_request(
+        r,
+        self_endpoint,
+        peer_connections,
         proxy_target,
-    ) {
+        balanced,
+        graceful,
+        url,
+        client,
+        None,
+    )
+    .await
+}
+
+/// Sends the captured request `id` of the inspected bridge of `url` again, to the peer it went
+/// to, and returns the id of the new exchange.
+pub async fn replay(
+    url: String,
+    id: u64,
+    profile: &str,
+    graceful: kulfi_utils::Graceful,
+) -> eyre::Result<u64> {
+    use http_body_util::BodyExt;
+
+    let (request, peer) = crate::inspect::replayable(&url, id)?;
+    let self_endpoint = crate::endpoint::get(profile).await?;
+    let peer_connections = crate::pool::connections();
+    let client = std::net::SocketAddr::from(([127, 0, 0, 1], 0));
+
+    let bridge = url.clone();
+    let (exchange, response) =
+        crate::inspect::capture(&bridge, Some(id), request, move |exchange| {
+            move |r| {
+                proxy_request(
+                    r,
+                    self_endpoint.clone(),
+                    peer_connections.clone(),
+                    Some(peer.clone()),
+                    None,
+                    graceful.clone(),
+                    url.clone(),
+                    client,
+                    Some(exchange),
+                )
+            }
+        })
+        .await;
+    // the response body is captured as it is read
+    response.into_body().collect().await?;
+    Ok(exchange)
+}
+
+/// Proxies `r` to its peer and records it in the bridge's access log, and for inspected bridges
+/// the peer of `exchange`.
+#[allow(clippy::too_many_arguments)]
+async fn proxy_request(
+    r: hyper::Request<hyper::body::Incoming>,
+    self_endpoint: iroh::Endpoint,
+    peer_connections: kulfi_utils::PeerStreamSenders,
+    proxy_target: Option<String>,
+    balanced: Option<crate::failover::Peers>,
+    graceful: kulfi_utils::Graceful,
+    url: String,
+    client: std::net::SocketAddr,
+    exchange: Option<u64>,
+) -> kulfi_utils::http::ProxyResult<eyre::Error> {
+    let started = std::time::Instant::now();
+    let mut access = access_entry(&r, url, client);
+
+    let limits = crate::http_limits::get(&access.url);
+    if let Some(error) = body_limit_exceeded(&r, limits.max_body_bytes) {
+        tracing::info!("rejected request body: {error}");
+        let page = crate::error_page::ErrorPage {
+            status: hyper::StatusCode::PAYLOAD_TOO_LARGE,
+            peer: None,
+            summary: "The request body is not accepted by this bridge.".to_string(),
+            error,
+            retry: access.path.clone(),
+        };
+        access.duration_ms = started.elapsed().as_millis() as u64;
+        return Ok(crate::access_log::record_response(access, page.response()));
+    }
+
+    // counts towards the peer's active connections until the response is back
+    let (proxy_target, _lease) = match &balanced {
+        Some(peers) => {
+            let (peer, lease) = peers.pick();
+            (Some(peer), Some(lease))
+        }
+        None => (proxy_target, None),
+    };
+
+    let host = r.headers().get("Host").and_then(|h| h.to_str().ok());
+    let peer_id = match get_peer_id52_from_host(host, proxy_target) {
         Ok(peer_id) => peer_id,
         Err(e) => {
             tracing::error!("failed to get peer id from request: {e:?}");
-            return Ok(kulfi_utils::bad_request!(
-                "failed to get peer id from request"
-            ));
+            let page = crate::error_page::ErrorPage {
+                // without a Host the request is malformed, with one it is not for this bridge
+                status: if host.is_some() {
+                    hyper::StatusCode::MISDIRECTED_REQUEST
+                } else {
+                    hyper::StatusCode::BAD_REQUEST
+                },
+                peer: None,
+                summary: format!(
+                    "This bridge does not serve the host {}.",
+                    host.unwrap_or("of this request")
+                ),
+                error: format!("{e:#}"),
+                retry: access.path.clone(),
+            };
+            access.duration_ms = started.elapsed().as_millis() as u64;
+            return Ok(crate::access_log::record_response(access, page.response()));
         }
     };
 
+    // the bridge as the browser addressed it, for rewriting the peer's own origin in the response
+    let local_host = host.map(str::to_string);
+
     tracing::info!("got request for {peer_id}");
+    // after the peer is picked, as that may go by the Host the browser sent
+    let mut r = r;
+    crate::headers::rewrite_request(&access.url, r.headers_mut());
+    if let Some(exchange) = exchange {
+        crate::inspect::set_peer(&access.url, exchange, &peer_id);
+    }
 
-    kulfi_utils::http_to_peer(
-        kulfi_utils::Protocol::Http.into(),
+    let response = to_peer(
         r,
+        limits.max_body_bytes,
         self_endpoint,
-        &peer_id,
+        peer_id.clone(),
         peer_connections,
         graceful,
-    )
-    .await
+    );
+    let result = match limits.response_timeout() {
+        Some(timeout) => tokio::time::timeout(timeout, response)
+            .await
+            .unwrap_or_else(|elapsed| Err(eyre::Error::new(elapsed))),
+        None => response.await,
+    };
+
+    // a balanced bridge leaves a peer it could not reach out of the rotation until it answers
+    if let Some(peers) = &balanced {
+        match &result {
+            Ok(_) => peers.report(&peer_id, true),
+            // a slow peer or a body over the limit say nothing about whether the peer is up
+            Err(e)
+                if !crate::error_page::is_timeout(e)
+                    && !crate::http_limits::is_body_too_large(e) =>
+            {
+                peers.report(&peer_id, false)
+            }
+            Err(_) => {}
+        }
+    }
+
+    let response = match result {
+        Ok(mut response) => {
+            if let Some(host) = &local_host {
+                crate::headers::rewrite_response(&access.url, host, response.headers_mut());
+            }
+            response
+        }
+        Err(e) if crate::http_limits::is_body_too_large(&e) => {
+            tracing::info!(peer = peer_id, "rejected request body: {e:#}");
+            crate::error_page::ErrorPage {
+                status: hyper::StatusCode::PAYLOAD_TOO_LARGE,
+                peer: Some(peer_id.clone()),
+                summary: "The request body is not accepted by this bridge.".to_string(),
+                error: format!(
+                    "the body is larger than the limit of {} bytes",
+                    limits.max_body_bytes.unwrap_or_default()
+                ),
+                retry: access.path.clone(),
+            }
+            .response()
+        }
+        Err(e) => {
+            tracing::error!(peer = peer_id, "failed to proxy request: {e:?}");
+            let timeout = crate::error_page::is_timeout(&e);
+            crate::error_page::ErrorPage {
+                status: if timeout {
+                    hyper::StatusCode::GATEWAY_TIMEOUT
+                } else {
+                    hyper::StatusCode::BAD_GATEWAY
+                },
+                peer: Some(peer_id.clone()),
+                summary: if timeout {
+                    "The peer did not answer in time.".to_string()
+                } else {
+                    "The peer could not be reached.".to_string()
+                },
+                error: format!("{e:#}"),
+                retry: access.path.clone(),
+            }
+            .response()
+        }
+    };
+    access.duration_ms = started.elapsed().as_millis() as u64;
+    access.peer = peer_id;
+
+    Ok(crate::access_log::record_response(access, response))
+}
+
+/// The access log entry of `r`, completed once the response is known.
+fn access_entry<B>(
+    r: &hyper::Request<B>,
+    url: String,
+    client: std::net::SocketAddr,
+) -> crate::access_log::AccessEntry {
+    let header = |name: hyper::header::HeaderName| {
+        r.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    crate::access_log::AccessEntry {
+        url,
+        time: std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        client: client.ip().to_string(),
+        method: r.method().to_string(),
+        path: r
+            .uri()
+            .path_and_query()
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "/".to_string()),
+        version: format!("{:?}", r.version()),
+        status: None,
+        bytes: 0,
+        duration_ms: 0,
+        peer: String::new(),
+        referer: header(hyper::header::REFERER),
+        user_agent: header(hyper::header::USER_AGENT),
+    }
+}
+
+/// Why `r` is rejected if its body is known to be longer than `max_body_bytes`, bodies of
+/// unknown length are cut off by `to_peer` once they grow past it.
+fn body_limit_exceeded<B: hyper::body::Body>(
+    r: &hyper::Request<B>,
+    max_body_bytes: Option<u64>,
+) -> Option<String> {
+    let max_body_bytes = max_body_bytes?;
+    r.body()
+        .size_hint()
+        .upper()
+        .filter(|len| *len > max_body_bytes)
+        .map(|len| {
+            format!("the body of {len} bytes is larger than the limit of {max_body_bytes} bytes")
+        })
+}
+
+/// Sends `r` to `peer_id`, failing with `http_body_util::LengthLimitError` in the chain if its
+/// body is of unknown length and grows past `max_body_bytes`.
+async fn to_peer(
+    r: hyper::Request<hyper::body::Incoming>,
+    max_body_bytes: Option<u64>,
+    self_endpoint: iroh::Endpoint,
+    peer_id: String,
+    peer_connections: kulfi_utils::PeerStreamSenders,
+    graceful: kulfi_utils::Graceful,
+) -> kulfi_utils::http::ProxyResult<eyre::Error> {
+    use http_body_util::BodyExt;
+
+    let max_body_bytes = match max_body_bytes {
+        Some(max_body_bytes) if r.body().size_hint().upper().is_none() => max_body_bytes,
+        // bodies of known length were checked by `body_limit_exceeded`
+        _ => {
+            return kulfi_utils::http_to_peer(
+                kulfi_utils::Protocol::Http.into(),
+                r,
+                self_endpoint,
+                &peer_id,
+                peer_connections,
+                graceful,
+            )
+            .await;
+        }
+    };
+
+    // http_to_peer only takes bodies read by hyper, so the limited body is read back over an
+    // in-memory connection
+    let limit = usize::try_from(max_body_bytes).unwrap_or(usize::MAX);
+    let r = r.map(|body| http_body_util::Limited::new(body, limit));
+    let response = crate::inspect::forward(r, move |r| {
+        let self_endpoint = self_endpoint.clone();
+        let peer_id = peer_id.clone();
+        let peer_connections = peer_connections.clone();
+        let graceful = graceful.clone();
+        async move {
+            kulfi_utils::http_to_peer(
+                kulfi_utils::Protocol::Http.into(),
+                r,
+                self_endpoint,
+                &peer_id,
+                peer_connections,
+                graceful,
+            )
+            .await
+        }
+    })
+    .await?;
+    Ok(response.map(|body| body.map_err(eyre::Report::from).boxed()))
 }
 
 fn get_peer_id52_from_host(
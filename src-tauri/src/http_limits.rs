@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Timeouts and size limits of an HTTP bridge, every one of them unlimited if unset, and the
+/// timeouts also if 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpLimits {
+    /// How long a client gets to send the headers of a request before its connection is closed.
+    pub header_timeout_secs: Option<u64>,
+    /// How long the peer gets to send the headers of its response, a 504 is sent after.
+    pub response_timeout_secs: Option<u64>,
+    /// How long a kept-alive connection may go without a request before it is closed.
+    pub idle_timeout_secs: Option<u64>,
+    /// Requests with longer bodies get a 413.
+    pub max_body_bytes: Option<u64>,
+}
+
+impl HttpLimits {
+    pub fn header_timeout(&self) -> Option<Duration> {
+        secs(self.header_timeout_secs)
+    }
+
+    pub fn response_timeout(&self) -> Option<Duration> {
+        secs(self.response_timeout_secs)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        secs(self.idle_timeout_secs)
+    }
+}
+
+fn secs(secs: Option<u64>) -> Option<Duration> {
+    secs.filter(|secs| *secs > 0).map(Duration::from_secs)
+}
+
+/// Whether `e` is a request body cut off for growing past the body limit.
+pub fn is_body_too_large(e: &eyre::Error) -> bool {
+    e.chain()
+        .any(|cause| cause.is::<http_body_util::LengthLimitError>())
+}
+
+/// The limits of every running HTTP bridge, by URL.
+static LIMITS: LazyLock<Mutex<HashMap<String, HttpLimits>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn set(url: &str, limits: HttpLimits) {
+    LIMITS
+        .lock()
+        .expect("Unable to unlock HTTP limits")
+        .insert(url.to_string(), limits);
+}
+
+/// The limits of the bridge of `url`, none if it was not given any.
+pub fn get(url: &str) -> HttpLimits {
+    LIMITS
+        .lock()
+        .expect("Unable to unlock HTTP limits")
+        .get(url)
+        .copied()
+        .unwrap_or_default()
+}
+
+pub fn forget(url: &str) {
+    LIMITS
+        .lock()
+        .expect("Unable to unlock HTTP limits")
+        .remove(url);
+}
+
+/// Tracks when a client connection last had a request in flight, so it can be closed once it
+/// idles past the idle timeout.
+#[derive(Debug, Clone)]
+pub struct ConnectionIdle {
+    in_flight: Arc<AtomicUsize>,
+    last_active: Arc<Mutex<Instant>>,
+}
+
+/// Counts as a request in flight until dropped.
+pub struct Busy(ConnectionIdle);
+
+impl Default for ConnectionIdle {
+    fn default() -> Self {
+        ConnectionIdle {
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            last_active: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+}
+
+impl ConnectionIdle {
+    pub fn busy(&self) -> Busy {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Busy(self.clone())
+    }
+
+    /// Resolves once no request was in flight for `timeout`, never without a timeout.
+    pub async fn idled(&self, timeout: Option<Duration>) {
+        let Some(timeout) = timeout else {
+            return std::future::pending().await;
+        };
+        loop {
+            let last_active = *self
+                .last_active
+                .lock()
+                .expect("Unable to unlock connection activity");
+            let wake = if self.in_flight.load(Ordering::SeqCst) > 0 {
+                Instant::now() + timeout / 4
+            } else if last_active.elapsed() >= timeout {
+                return;
+            } else {
+                last_active + timeout
+            };
+            tokio::time::sleep_until(wake).await;
+        }
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        *self
+            .0
+            .last_active
+            .lock()
+            .expect("Unable to unlock connection activity") = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_and_zero_timeouts_are_unlimited() {
+        let limits = HttpLimits {
+            header_timeout_secs: Some(0),
+            response_timeout_secs: None,
+            idle_timeout_secs: Some(90),
+            max_body_bytes: None,
+        };
+        assert_eq!(limits.header_timeout(), None);
+        assert_eq!(limits.response_timeout(), None);
+        assert_eq!(limits.idle_timeout(), Some(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn missing_fields_are_unset() {
+        let limits: HttpLimits = serde_json::from_str(r#"{"responseTimeoutSecs": 60}"#).unwrap();
+        assert_eq!(
+            limits,
+            HttpLimits {
+                response_timeout_secs: Some(60),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn bridges_without_limits_get_none() {
+        let url = "http://127.0.0.1:1/test-http-limits";
+        assert_eq!(get(url), HttpLimits::default());
+
+        let limits = HttpLimits {
+            max_body_bytes: Some(1024),
+            ..Default::default()
+        };
+        set(url, limits);
+        assert_eq!(get(url), limits);
+        forget(url);
+        assert_eq!(get(url), HttpLimits::default());
+    }
+
+    #[tokio::test]
+    async fn reports_bodies_growing_past_the_limit() {
+        use http_body_util::BodyExt;
+
+        let forward = |body: &'static str| {
+            let r = hyper::Request::builder()
+                .method("POST")
+                .uri("http://127.0.0.1/upload")
+                .body(http_body_util::Limited::new(
+                    http_body_util::Full::new(hyper::body::Bytes::from(body)),
+                    4,
+                ))
+                .unwrap();
+            crate::inspect::forward(r, |r: hyper::Request<hyper::body::Incoming>| async move {
+                r.into_body().collect().await?;
+                let empty = http_body_util::Empty::<hyper::body::Bytes>::new();
+                Ok::<_, eyre::Error>(hyper::Response::new(
+                    empty
+                        .map_err(|never| -> eyre::Error { match never {} })
+                        .boxed(),
+                ))
+            })
+        };
+
+        assert!(forward("1234").await.is_ok());
+        let e = forward("12345").await.unwrap_err();
+        assert!(is_body_too_large(&e));
+        assert!(!is_body_too_large(&eyre::anyhow!("connection refused")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idles_only_without_requests_in_flight() {
+        let idle = ConnectionIdle::default();
+        let timeout = Some(Duration::from_secs(10));
+
+        let busy = idle.busy();
+        let still_busy = tokio::time::timeout(Duration::from_secs(60), idle.idled(timeout)).await;
+        assert!(still_busy.is_err());
+
+        drop(busy);
+        let idled = tokio::time::timeout(Duration::from_secs(11), idle.idled(timeout)).await;
+        assert!(idled.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn never_idles_without_a_timeout() {
+        let idle = ConnectionIdle::default();
+        let idled = tokio::time::timeout(Duration::from_secs(3600), idle.idled(None)).await;
+        assert!(idled.is_err());
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+/// File in the profile directory holding its secret key, in the same hex format as malai's
+/// `.malai.secret-key`.
+const SECRET_KEY_FILE: &str = "secret-key";
+
+/// Prefix of a password protected export: `malai-key1.<salt>.<nonce>.<ciphertext>`, each part
+/// base64url encoded.
+const EXPORT_PREFIX: &str = "malai-key1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// The secret key of every loaded profile, by profile name.
+static SECRET_KEYS: LazyLock<Mutex<BTreeMap<String, kulfi_id52::SecretKey>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// Why the secret key of a profile could not be loaded, by profile name. Such a profile has no
+/// identity until a key is imported or generated for it, so peers never see a temporary id52.
+static LOAD_ERRORS: LazyLock<Mutex<BTreeMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// The id52 of a profile, what peers see and put in their allowlists.
+pub fn id52(profile: &str) -> Option<String> {
+    SECRET_KEYS
+        .lock()
+        .expect("Unable to unlock secret keys")
+        .get(profile)
+        .map(|secret_key| secret_key.id52())
+}
+
+/// The id52 of every loaded profile, by profile name.
+pub fn all() -> Vec<(String, String)> {
+    SECRET_KEYS
+        .lock()
+        .expect("Unable to unlock secret keys")
+        .iter()
+        .map(|(profile, secret_key)| (profile.clone(), secret_key.id52()))
+        .collect()
+}
+
+/// The profiles whose secret key could not be loaded, with the reason.
+pub fn load_errors() -> Vec<(String, String)> {
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock identity load errors")
+        .iter()
+        .map(|(profile, error)| (profile.clone(), error.clone()))
+        .collect()
+}
+
+/// Why the secret key of `profile` could not be loaded, if it could not.
+pub fn load_error(profile: &str) -> Option<String> {
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock identity load errors")
+        .get(profile)
+        .cloned()
+}
+
+/// The secret key for `crate::endpoint` to build the endpoint of a profile with.
+pub fn secret_key(profile: &str) -> eyre::Result<iroh::SecretKey> {
+    if let Some(error) = load_error(profile) {
+        return Err(eyre::anyhow!(
+            "the identity of profile {profile} could not be loaded: {error}"
+        ));
+    }
+    SECRET_KEYS
+        .lock()
+        .expect("Unable to unlock secret keys")
+        .get(profile)
+        .map(|secret_key| iroh::SecretKey::from_bytes(&secret_key.to_bytes()))
+        .ok_or_else(|| eyre::anyhow!("profile {profile} does not exist"))
+}
+
+/// Loads the persisted secret key of a profile, generating and saving one the first time.
+///
+/// If that fails the profile is kept without an identity and the error is reported by
+/// `load_error`, bridges of the profile fail to start until a key is imported or generated.
+pub fn load(app_handle: &tauri::AppHandle, profile: &str) -> eyre::Result<()> {
+    match load_(app_handle, profile) {
+        Ok(secret_key) => {
+            tracing::info!(profile, id52 = secret_key.id52(), "loaded identity");
+            SECRET_KEYS
+                .lock()
+                .expect("Unable to unlock secret keys")
+                .insert(profile.to_string(), secret_key);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(profile, "failed to load identity: {e:?}");
+            LOAD_ERRORS
+                .lock()
+                .expect("Unable to unlock identity load errors")
+                .insert(profile.to_string(), format!("{e:#}"));
+            Err(e)
+        }
+    }
+}
+
+pub fn forget(profile: &str) {
+    SECRET_KEYS
+        .lock()
+        .expect("Unable to unlock secret keys")
+        .remove(profile);
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock identity load errors")
+        .remove(profile);
+}
+
+fn load_(app_handle: &tauri::AppHandle, profile: &str) -> eyre::Result<kulfi_id52::SecretKey> {
+    let path = key_path(app_handle, profile)?;
+    let secret_key = match std::fs::read_to_string(&path) {
+        Ok(secret) => parse_secret_key(secret.trim())?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let secret_key = kulfi_id52::SecretKey::generate();
+            save(&path, &secret_key)?;
+            secret_key
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(secret_key)
+}
+
+/// Generates a fresh secret key for a profile and switches to it, returning the new id52.
+pub async fn rotate(app_handle: &tauri::AppHandle, profile: &str) -> eyre::Result<String> {
+    switch(app_handle, profile, kulfi_id52::SecretKey::generate()).await
+}
+
+/// Switches a profile to an imported secret key, returning its id52.
+///
+/// `key` is either an `export`ed key, which needs its `password`, or a plain secret key as
+/// found in malai's `.malai.secret-key` or `KULFI_SECRET_KEY`.
+pub async fn import(
+    app_handle: &tauri::AppHandle,
+    profile: &str,
+    key: &str,
+    password: Option<&str>,
+) -> eyre::Result<String> {
+    let secret_key = decode(key.trim(), password)?;
+    switch(app_handle, profile, secret_key).await
+}
+
+/// Encrypts the secret key of a profile with `password` for `import` on another install.
+pub fn export(profile: &str, password: &str) -> eyre::Result<String> {
+    use chacha20poly1305::aead::{Aead, AeadCore, OsRng, rand_core::RngCore};
+
+    if password.is_empty() {
+        return Err(eyre::anyhow!(
+            "a password is required to export the secret key"
+        ));
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = chacha20poly1305::ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let secret = SECRET_KEYS
+        .lock()
+        .expect("Unable to unlock secret keys")
+        .get(profile)
+        .ok_or_else(|| eyre::anyhow!("profile {profile} does not exist"))?
+        .to_bytes();
+    let ciphertext = cipher(password, &salt)?
+        .encrypt(&nonce, secret.as_slice())
+        .map_err(|e| eyre::anyhow!("failed to encrypt secret key: {e}"))?;
+
+    let b64 = |bytes: &[u8]| data_encoding::BASE64URL_NOPAD.encode(bytes);
+    Ok(format!(
+        "{EXPORT_PREFIX}.{}.{}.{}",
+        b64(&salt),
+        b64(&nonce),
+        b64(&ciphertext)
+    ))
+}
+
+/// Persists `secret_key` for a profile and rebuilds its endpoint, running bridges reconnect with
+/// the new identity.
+async fn switch(
+    app_handle: &tauri::AppHandle,
+    profile: &str,
+    secret_key: kulfi_id52::SecretKey,
+) -> eyre::Result<String> {
+    if !crate::profile::exists(profile) {
+        return Err(eyre::anyhow!("profile {profile} does not exist"));
+    }
+    save(&key_path(app_handle, profile)?, &secret_key)?;
+
+    let id52 = secret_key.id52();
+    SECRET_KEYS
+        .lock()
+        .expect("Unable to unlock secret keys")
+        .insert(profile.to_string(), secret_key);
+    // the profile has an identity again
+    LOAD_ERRORS
+        .lock()
+        .expect("Unable to unlock identity load errors")
+        .remove(profile);
+    tracing::info!(profile, id52, "switched identity");
+
+    crate::endpoint::restart(profile).await?;
+    Ok(id52)
+}
+
+fn decode(key: &str, password: Option<&str>) -> eyre::Result<kulfi_id52::SecretKey> {
+    use chacha20poly1305::aead::Aead;
+
+    let Some(parts) = key
+        .strip_prefix(EXPORT_PREFIX)
+        .and_then(|rest| rest.strip_prefix('.'))
+    else {
+        return parse_secret_key(key);
+    };
+
+    let password = password
+        .filter(|p| !p.is_empty())
+        .ok_or_else(|| eyre::anyhow!("this key is password protected"))?;
+
+    let parts = parts
+        .split('.')
+        .map(|part| data_encoding::BASE64URL_NOPAD.decode(part.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| eyre::anyhow!("malformed exported key: {e}"))?;
+    let [salt, nonce, ciphertext] = parts.as_slice() else {
+        return Err(eyre::anyhow!("malformed exported key"));
+    };
+    if nonce.len() != NONCE_LEN {
+        return Err(eyre::anyhow!("malformed exported key"));
+    }
+
+    let secret = cipher(password, salt)?
+        .decrypt(
+            chacha20poly1305::Nonce::from_slice(nonce),
+            ciphertext.as_slice(),
+        )
+        .map_err(|_| eyre::anyhow!("wrong password or corrupted key"))?;
+    let secret: [u8; 32] = secret
+        .try_into()
+        .map_err(|_| eyre::anyhow!("exported key has an invalid length"))?;
+    Ok(kulfi_id52::SecretKey::from_bytes(&secret))
+}
+
+/// Helper to derive the export cipher from a password with argon2id.
+fn cipher(password: &str, salt: &[u8]) -> eyre::Result<chacha20poly1305::ChaCha20Poly1305> {
+    use chacha20poly1305::KeyInit;
+
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| eyre::anyhow!("failed to derive key from password: {e}"))?;
+    Ok(chacha20poly1305::ChaCha20Poly1305::new(&key.into()))
+}
+
+fn parse_secret_key(secret: &str) -> eyre::Result<kulfi_id52::SecretKey> {
+    use std::str::FromStr;
+
+    kulfi_id52::SecretKey::from_str(secret).map_err(|e| eyre::anyhow!("invalid secret key: {e}"))
+}
+
+fn key_path(app_handle: &tauri::AppHandle, profile: &str) -> eyre::Result<std::path::PathBuf> {
+    Ok(crate::profile::dir(app_handle, profile)?.join(SECRET_KEY_FILE))
+}
+
+/// Helper to write the secret key readable by the current user only.
+///
+/// The key is written to a temporary file next to it and renamed over it, so a crash never
+/// leaves a truncated key behind.
+fn save(path: &std::path::Path, secret_key: &kulfi_id52::SecretKey) -> eyre::Result<()> {
+    use std::io::Write;
+
+    let tmp = path.with_extension("tmp");
+    // left over by a crash, it may have been created with other permissions
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&tmp)?;
+    file.write_all(secret_key.to_string().as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp, path)?;
+
+    // the rename only survives a crash once the directory is synced too
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helper to export a fresh key through a profile only this test uses.
+    fn exported(profile: &str, password: &str) -> (String, String) {
+        let secret_key = kulfi_id52::SecretKey::generate();
+        let id52 = secret_key.id52();
+        SECRET_KEYS
+            .lock()
+            .expect("Unable to unlock secret keys")
+            .insert(profile.to_string(), secret_key);
+        let key = export(profile, password).unwrap();
+        forget(profile);
+        (id52, key)
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let (id52, key) = exported("test-round-trip", "hunter2");
+        assert!(key.starts_with("malai-key1."));
+        assert_eq!(decode(&key, Some("hunter2")).unwrap().id52(), id52);
+    }
+
+    #[test]
+    fn rejects_a_wrong_password() {
+        let (_, key) = exported("test-wrong-password", "hunter2");
+        let error = decode(&key, Some("hunter3")).unwrap_err();
+        assert!(error.to_string().contains("wrong password"), "{error}");
+        assert!(decode(&key, None).is_err());
+    }
+
+    #[test]
+    fn rejects_a_corrupted_key() {
+        let (_, key) = exported("test-corrupted", "hunter2");
+
+        let (rest, last) = key.split_at(key.len() - 1);
+        let flipped = if last == "A" { "B" } else { "A" };
+        assert!(decode(&format!("{rest}{flipped}"), Some("hunter2")).is_err());
+
+        let truncated = key.rsplit_once('.').unwrap().0;
+        assert!(decode(truncated, Some("hunter2")).is_err());
+        assert!(decode(&format!("{key}!"), Some("hunter2")).is_err());
+    }
+
+    #[test]
+    fn rejects_exporting_without_password() {
+        assert!(export("test-no-password", "").is_err());
+    }
+
+    #[test]
+    fn plain_keys_import_without_password() {
+        let secret_key = kulfi_id52::SecretKey::generate();
+        let key = secret_key.to_string();
+        assert_eq!(decode(&key, None).unwrap().id52(), secret_key.id52());
+        assert!(decode("not a key", None).is_err());
+    }
+
+    #[test]
+    fn save_replaces_the_key() {
+        let dir = std::env::temp_dir().join(format!("malai-identity-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(SECRET_KEY_FILE);
+
+        for _ in 0..2 {
+            let secret_key = kulfi_id52::SecretKey::generate();
+            save(&path, &secret_key).unwrap();
+            let saved = parse_secret_key(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+            assert_eq!(saved.id52(), secret_key.id52());
+        }
+        assert!(!path.with_extension("tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
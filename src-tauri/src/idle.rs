@@ -0,0 +1,253 @@
+use crate::lifecycle::BridgeState;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Whether a bridge holds connections to its peers, and for on-demand bridges when it last had
+/// local traffic.
+///
+/// Eager bridges dial their peers when they start and hold the connections until they stop.
+/// On-demand bridges, those with an idle period, only dial when a local client shows up and close
+/// the connections once there was no open local connection or datagram for that long. Clones
+/// share their state.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    url: String,
+    /// Whose identity the bridge dials from.
+    profile: String,
+    /// The peers the bridge may dial, the one of its URL first.
+    peers: Vec<String>,
+    idle_after: Option<Duration>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    started: bool,
+    connected: bool,
+    connections: usize,
+    last_used: Instant,
+    idle_check: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// Marks a local connection of an on-demand bridge as open until dropped.
+pub struct Open {
+    activity: Activity,
+}
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        let mut state = self.activity.lock();
+        state.connections -= 1;
+        state.last_used = Instant::now();
+    }
+}
+
+impl Activity {
+    /// `peers` must not be empty. Without `idle_secs` the bridge is eager.
+    pub fn new(url: &str, profile: &str, peers: &[String], idle_secs: Option<u64>) -> Self {
+        assert!(!peers.is_empty(), "a bridge needs at least one peer");
+        Activity {
+            inner: Arc::new(Inner {
+                url: url.to_string(),
+                profile: profile.to_string(),
+                peers: peers.to_vec(),
+                idle_after: idle_secs.filter(|secs| *secs > 0).map(Duration::from_secs),
+                state: Mutex::new(State {
+                    started: false,
+                    connected: false,
+                    connections: 0,
+                    last_used: Instant::now(),
+                    idle_check: None,
+                }),
+            }),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.inner.url
+    }
+
+    /// The peer of the bridge's URL.
+    pub fn id52(&self) -> &str {
+        &self.inner.peers[0]
+    }
+
+    pub fn peers(&self) -> &[String] {
+        &self.inner.peers
+    }
+
+    /// Whether an on-demand bridge currently has no peer connections.
+    pub fn is_idle(&self) -> bool {
+        !self.lock().connected
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .expect("Unable to unlock bridge activity")
+    }
+
+    /// Called once the bridge listens. Eager bridges take their peer connections right away,
+    /// on-demand ones report they are idle.
+    pub fn start(&self) {
+        let mut state = self.lock();
+        state.started = true;
+        match self.inner.idle_after {
+            None => self.connect(&mut state),
+            Some(_) => crate::lifecycle::emit(&self.inner.url, BridgeState::Idle, None),
+        }
+    }
+
+    /// Lets go of the peer connections, called when the bridge stops.
+    pub fn stop(&self) {
+        let mut state = self.lock();
+        state.started = false;
+        if let Some(task) = state.idle_check.take() {
+            task.abort();
+        }
+        self.disconnect(&mut state, false);
+    }
+
+    /// Marks a local connection as open, waking an idle on-demand bridge.
+    pub fn open(&self) -> Open {
+        self.touch();
+        self.lock().connections += 1;
+        Open {
+            activity: self.clone(),
+        }
+    }
+
+    /// Records local traffic that has no connection to hold open, like a UDP datagram.
+    pub fn touch(&self) {
+        let mut state = self.lock();
+        state.last_used = Instant::now();
+        if state.started && !state.connected {
+            tracing::info!(url = self.inner.url, "waking idle bridge");
+            self.connect(&mut state);
+            crate::lifecycle::emit(&self.inner.url, BridgeState::Running, None);
+        }
+    }
+
+    fn connect(&self, state: &mut State) {
+        state.connected = true;
+        self.inner
+            .peers
+            .iter()
+            .for_each(|peer| crate::pool::acquire(&self.inner.profile, peer));
+        if let Some(idle_after) = self.inner.idle_after {
+            state.idle_check = Some(tokio::spawn(idle_check(self.clone(), idle_after)));
+        }
+    }
+
+    /// `now` closes the connections right away, otherwise they stay open for a while in case the
+    /// bridge is started again.
+    fn disconnect(&self, state: &mut State, now: bool) {
+        if !std::mem::take(&mut state.connected) {
+            return;
+        }
+        for peer in &self.inner.peers {
+            if now {
+                crate::pool::release_now(&self.inner.profile, peer);
+            } else {
+                crate::pool::release(&self.inner.profile, peer);
+            }
+        }
+    }
+}
+
+/// Closes the peer connections of an on-demand bridge once it had no local traffic for
+/// `idle_after`.
+async fn idle_check(activity: Activity, idle_after: Duration) {
+    let mut wait = idle_after;
+    loop {
+        tokio::time::sleep(wait).await;
+
+        let mut state = activity.lock();
+        let idle_for = state.last_used.elapsed();
+        if state.connections > 0 {
+            wait = idle_after;
+        } else if idle_for < idle_after {
+            wait = idle_after - idle_for;
+        } else {
+            tracing::info!(url = activity.inner.url, "bridge went idle");
+            state.idle_check = None;
+            activity.disconnect(&mut state, true);
+            crate::lifecycle::emit(&activity.inner.url, BridgeState::Idle, None);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(profile: &str, id52: &str) -> Option<usize> {
+        crate::pool::held()
+            .into_iter()
+            .find(|held| held.profile == profile && held.id52 == id52)
+            .map(|held| held.bridges)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn eager_bridges_dial_on_start() {
+        let peers = ["test-eager-a", "test-eager-b"].map(str::to_string);
+        let activity = Activity::new("kulfi://test-eager-a", "test-idle-eager", &peers, None);
+        assert_eq!(held("test-idle-eager", "test-eager-a"), None);
+
+        activity.start();
+        assert!(!activity.is_idle());
+        assert_eq!(held("test-idle-eager", "test-eager-a"), Some(1));
+        assert_eq!(held("test-idle-eager", "test-eager-b"), Some(1));
+
+        // no local traffic does not matter
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+        assert!(!activity.is_idle());
+        assert_eq!(held("test-idle-eager", "test-eager-a"), Some(1));
+
+        activity.stop();
+        assert!(activity.is_idle());
+        // kept open for a while in case the bridge is started again
+        assert_eq!(held("test-idle-eager", "test-eager-a"), Some(0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn on_demand_bridges_dial_on_traffic_and_close_when_idle() {
+        let peers = ["test-on-demand".to_string()];
+        let activity = Activity::new("kulfi://test-on-demand", "test-idle", &peers, Some(60));
+
+        activity.start();
+        assert!(activity.is_idle());
+        assert_eq!(held("test-idle", "test-on-demand"), None);
+
+        let open = activity.open();
+        assert!(!activity.is_idle());
+        assert_eq!(held("test-idle", "test-on-demand"), Some(1));
+
+        // an open local connection keeps the bridge busy
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert!(!activity.is_idle());
+
+        drop(open);
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        activity.touch();
+        tokio::time::sleep(Duration::from_secs(45)).await;
+        assert!(!activity.is_idle());
+
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert!(activity.is_idle());
+        assert_eq!(held("test-idle", "test-on-demand"), None);
+
+        // and wakes up again
+        activity.touch();
+        assert_eq!(held("test-idle", "test-on-demand"), Some(1));
+        activity.stop();
+    }
+}
@@ -0,0 +1,568 @@
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Bytes, Frame, Incoming, SizeHint};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::task::{Context, Poll, ready};
+use std::time::{Instant, SystemTime};
+
+/// How much of each request and response body the inspector keeps.
+pub const BODY_LIMIT: usize = 16 * 1024;
+/// How many exchanges of each bridge are kept.
+const CAPACITY: usize = 200;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The headers and the start of the body of a request or a response. The body is left out of
+/// events, the inspector fetches it with `bodies` for the exchange it shows.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Captured {
+    pub headers: Vec<(String, String)>,
+    /// Size of the whole body seen so far.
+    pub body_len: u64,
+    /// Whether the body was longer than the kept prefix.
+    pub truncated: bool,
+    /// Set once the whole body went through, unset while it is still streaming or if it was cut
+    /// short by an error or a client going away.
+    pub complete: bool,
+    #[serde(skip)]
+    raw: Vec<u8>,
+    #[serde(skip)]
+    limit: usize,
+}
+
+/// A request proxied by an inspected HTTP bridge and its response, sent to the frontend without
+/// the bodies as `http-exchange` events whenever it progresses.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exchange {
+    pub id: u64,
+    pub url: String,
+    /// Unix time the request came in, in milliseconds.
+    pub time: u64,
+    /// The id52 the request was proxied to, once picked.
+    pub peer: Option<String>,
+    /// The exchange this one replayed.
+    pub replay_of: Option<u64>,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub request: Captured,
+    pub status: Option<u16>,
+    pub response: Option<Captured>,
+    /// Until the response headers were back.
+    pub duration_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// The kept bodies of an exchange, decoded as UTF-8 where possible.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bodies {
+    pub request: String,
+    /// Unset until the response headers were back.
+    pub response: Option<String>,
+}
+
+struct Inspected {
+    exchanges: VecDeque<Exchange>,
+    /// How much of the bodies in flight is kept, the finished ones are cut to `BODY_LIMIT`.
+    body_limit: usize,
+}
+
+/// The exchanges of every inspected bridge, by URL.
+static EXCHANGES: LazyLock<Mutex<HashMap<String, Inspected>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Starts inspecting the bridge of `url` from scratch, keeping up to `body_limit` of each body
+/// until the exchange is finished, or without a limit stops and forgets its exchanges.
+pub fn start(url: &str, body_limit: Option<usize>) {
+    let mut exchanges = EXCHANGES.lock().expect("Unable to unlock exchanges");
+    match body_limit {
+        Some(body_limit) => {
+            exchanges.insert(
+                url.to_string(),
+                Inspected {
+                    exchanges: VecDeque::with_capacity(CAPACITY),
+                    body_limit,
+                },
+            );
+        }
+        None => {
+            exchanges.remove(url);
+        }
+    }
+}
+
+pub fn is_enabled(url: &str) -> bool {
+    EXCHANGES
+        .lock()
+        .expect("Unable to unlock exchanges")
+        .contains_key(url)
+}
+
+/// Upgraded connections like WebSockets are passed through uninspected, the inspector only
+/// speaks plain request and response.
+pub fn is_upgrade<B>(r: &hyper::Request<B>) -> bool {
+    r.headers().contains_key(hyper::header::UPGRADE)
+}
+
+/// The exchanges of the bridge of `url`, oldest first.
+pub fn exchanges(url: &str) -> Vec<Exchange> {
+    EXCHANGES
+        .lock()
+        .expect("Unable to unlock exchanges")
+        .get(url)
+        .map(|inspected| inspected.exchanges.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// The start of the bodies of exchange `id` of the bridge of `url`, up to what the inspector
+/// keeps of finished exchanges.
+pub fn bodies(url: &str, id: u64) -> eyre::Result<Bodies> {
+    let text = |captured: &Captured| {
+        let raw = captured.raw();
+        String::from_utf8_lossy(&raw[..raw.len().min(BODY_LIMIT)]).into_owned()
+    };
+    let exchanges = EXCHANGES.lock().expect("Unable to unlock exchanges");
+    let exchange = exchanges
+        .get(url)
+        .and_then(|inspected| {
+            inspected
+                .exchanges
+                .iter()
+                .find(|exchange| exchange.id == id)
+        })
+        .ok_or_else(|| eyre::anyhow!("no captured request {id} for {url}"))?;
+    Ok(Bodies {
+        request: text(&exchange.request),
+        response: exchange.response.as_ref().map(text),
+    })
+}
+
+/// Records the peer exchange `id` of the bridge of `url` was proxied to.
+pub fn set_peer(url: &str, id: u64, peer: &str) {
+    update(url, id, |exchange| exchange.peer = Some(peer.to_string()));
+}
+
+/// Rebuilds the captured request `id` of the bridge of `url` along with the peer it went to,
+/// only requests whose whole body was kept can be replayed.
+pub fn replayable(url: &str, id: u64) -> eyre::Result<(hyper::Request<Full<Bytes>>, String)> {
+    let exchanges = EXCHANGES.lock().expect("Unable to unlock exchanges");
+    let exchange = exchanges
+        .get(url)
+        .and_then(|inspected| {
+            inspected
+                .exchanges
+                .iter()
+                .find(|exchange| exchange.id == id)
+        })
+        .ok_or_else(|| eyre::anyhow!("no captured request {id} for {url}"))?;
+    let peer = exchange
+        .peer
+        .clone()
+        .ok_or_else(|| eyre::anyhow!("request {id} never reached a peer"))?;
+    if !exchange.request.complete || exchange.request.truncated {
+        return Err(eyre::anyhow!(
+            "the body of request {id} was not captured in full"
+        ));
+    }
+
+    let mut builder = hyper::Request::builder()
+        .method(exchange.method.as_str())
+        .uri(exchange.uri.as_str());
+    for (name, value) in &exchange.request.headers {
+        builder = builder.header(name, value);
+    }
+    let request = builder.body(Full::new(Bytes::from(exchange.request.raw.clone())))?;
+    Ok((request, peer))
+}
+
+/// Proxies `req` through `service` while capturing it and its response as a new exchange of the
+/// bridge of `url`, returns the exchange's id and the response.
+///
+/// The request is handed to `service` over an in-memory HTTP/1.1 connection, so its body is
+/// captured as it streams instead of being buffered. `make_service` gets the exchange's id to
+/// report the peer with.
+pub async fn capture<B, F, S, Fut>(
+    url: &str,
+    replay_of: Option<u64>,
+    req: hyper::Request<B>,
+    make_service: F,
+) -> (u64, hyper::Response<BoxBody<Bytes, eyre::Error>>)
+where
+    B: Body<Data = Bytes> + Unpin + Send + 'static,
+    B::Error: Into<BoxError>,
+    F: FnOnce(u64) -> S,
+    S: Fn(hyper::Request<Incoming>) -> Fut + Send + 'static,
+    Fut: Future<Output = kulfi_utils::http::ProxyResult<eyre::Error>> + Send + 'static,
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let started = Instant::now();
+    let limit = EXCHANGES
+        .lock()
+        .expect("Unable to unlock exchanges")
+        .get(url)
+        .map_or(BODY_LIMIT, |inspected| inspected.body_limit);
+    let request = Captured::new(req.headers(), limit);
+    insert(Exchange {
+        id,
+        url: url.to_string(),
+        time: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default(),
+        peer: None,
+        replay_of,
+        method: req.method().to_string(),
+        uri: req.uri().to_string(),
+        version: format!("{:?}", req.version()),
+        request: request.clone(),
+        status: None,
+        response: None,
+        duration_ms: None,
+        error: None,
+    });
+
+    let retry = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| "/".to_string(), |p| p.to_string());
+    let req = req.map({
+        let url = url.to_string();
+        move |body| Tee::new(body, request, move |c| update(&url, id, |e| e.request = c))
+    });
+
+    match forward(req, make_service(id)).await {
+        Ok(response) => {
+            let (parts, body) = response.into_parts();
+            let captured = Captured::new(&parts.headers, limit);
+            update(url, id, |e| {
+                e.status = Some(parts.status.as_u16());
+                e.duration_ms = Some(started.elapsed().as_millis() as u64);
+                e.response = Some(captured.clone());
+            });
+            let url = url.to_string();
+            let body = Tee::new(body.map_err(eyre::Report::from), captured, move |c| {
+                update(&url, id, |e| e.response = Some(c));
+                finish(&url, id);
+            });
+            (id, hyper::Response::from_parts(parts, BoxBody::new(body)))
+        }
+        Err(e) => {
+            tracing::error!(url, "failed to proxy inspected request: {e:?}");
+            update(url, id, |exchange| {
+                exchange.error = Some(e.to_string());
+                exchange.duration_ms = Some(started.elapsed().as_millis() as u64);
+            });
+            let page = crate::error_page::ErrorPage {
+                status: hyper::StatusCode::BAD_GATEWAY,
+                peer: None,
+                summary: "The inspector could not pass the request on to the bridge.".to_string(),
+                error: format!("{e:#}"),
+                retry,
+            };
+            (id, page.response())
+        }
+    }
+}
+
+/// Sends `req` to `service` over an in-memory HTTP/1.1 connection, which turns any body into the
+/// `Incoming` body `kulfi_utils::http_to_peer` takes.
+pub async fn forward<B, S, Fut>(
+    mut req: hyper::Request<B>,
+    service: S,
+) -> eyre::Result<hyper::Response<Incoming>>
+where
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    S: Fn(hyper::Request<Incoming>) -> Fut + Send + 'static,
+    Fut: Future<Output = kulfi_utils::http::ProxyResult<eyre::Error>> + Send + 'static,
+{
+    // the in-memory connection is HTTP/1.1 whatever the client spoke
+    if !req.headers().contains_key(hyper::header::HOST)
+        && let Some(authority) = req.uri().authority()
+        && let Ok(host) = authority.as_str().parse()
+    {
+        req.headers_mut().insert(hyper::header::HOST, host);
+    }
+    *req.version_mut() = hyper::Version::HTTP_11;
+
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        if let Err(e) = hyper::server::conn::http1::Builder::new()
+            .serve_connection(
+                hyper_util::rt::TokioIo::new(server),
+                hyper::service::service_fn(service),
+            )
+            .await
+        {
+            tracing::error!("inspected connection error: {e:?}");
+        }
+    });
+
+    let (mut sender, conn) =
+        hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(client)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.await {
+            tracing::error!("inspected connection error: {e:?}");
+        }
+    });
+    Ok(sender.send_request(req).await?)
+}
+
+fn insert(exchange: Exchange) {
+    let event = {
+        let mut exchanges = EXCHANGES.lock().expect("Unable to unlock exchanges");
+        let Some(inspected) = exchanges.get_mut(&exchange.url) else {
+            return;
+        };
+        if inspected.exchanges.len() == CAPACITY {
+            inspected.exchanges.pop_front();
+        }
+        let event = serde_json::to_value(&exchange);
+        inspected.exchanges.push_back(exchange);
+        event
+    };
+    emit(event);
+}
+
+fn update(url: &str, id: u64, f: impl FnOnce(&mut Exchange)) {
+    // serialized in place, cloning would copy the kept bodies
+    let event = {
+        let mut exchanges = EXCHANGES.lock().expect("Unable to unlock exchanges");
+        let Some(exchange) = find(&mut exchanges, url, id) else {
+            return;
+        };
+        f(exchange);
+        serde_json::to_value(&*exchange)
+    };
+    emit(event);
+}
+
+fn emit(event: serde_json::Result<serde_json::Value>) {
+    match event {
+        Ok(event) => crate::events::emit("http-exchange", event),
+        Err(e) => tracing::error!("failed to serialize exchange: {e:?}"),
+    }
+}
+
+/// Hands the exchange whose response just ended to a HAR recording of the bridge, if any, and
+/// cuts its bodies down to what the inspector keeps.
+fn finish(url: &str, id: u64) {
+    let exchange = {
+        let mut exchanges = EXCHANGES.lock().expect("Unable to unlock exchanges");
+        let Some(exchange) = find(&mut exchanges, url, id) else {
+            return;
+        };
+        let finished = exchange.clone();
+        exchange.request.cut(BODY_LIMIT);
+        if let Some(response) = exchange.response.as_mut() {
+            response.cut(BODY_LIMIT);
+        }
+        finished
+    };
+    crate::har::record(&exchange);
+}
+
+fn find<'a>(
+    exchanges: &'a mut HashMap<String, Inspected>,
+    url: &str,
+    id: u64,
+) -> Option<&'a mut Exchange> {
+    exchanges
+        .get_mut(url)?
+        .exchanges
+        .iter_mut()
+        .find(|exchange| exchange.id == id)
+}
+
+impl Captured {
+    fn new(headers: &hyper::HeaderMap, limit: usize) -> Self {
+        Captured {
+            headers: headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect(),
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// The kept prefix of the body.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.body_len += data.len() as u64;
+        let room = self.limit - self.raw.len();
+        if data.len() > room {
+            self.truncated = true;
+        }
+        self.raw.extend_from_slice(&data[..data.len().min(room)]);
+    }
+
+    fn cut(&mut self, limit: usize) {
+        if self.raw.len() > limit {
+            self.raw.truncate(limit);
+            self.truncated = true;
+        }
+    }
+}
+
+/// A body passed through as is while the start of it is kept, `done` gets what was kept once
+/// the body ends or is dropped.
+struct Tee<B: Body> {
+    inner: B,
+    captured: Captured,
+    done: Option<Box<dyn FnOnce(Captured) + Send + Sync>>,
+}
+
+impl<B: Body> Tee<B> {
+    fn new(
+        inner: B,
+        captured: Captured,
+        done: impl FnOnce(Captured) + Send + Sync + 'static,
+    ) -> Self {
+        Tee {
+            inner,
+            captured,
+            done: Some(Box::new(done)),
+        }
+    }
+
+    fn finish(&mut self, complete: bool) {
+        if let Some(done) = self.done.take() {
+            let mut captured = std::mem::take(&mut self.captured);
+            captured.complete = complete;
+            done(captured);
+        }
+    }
+}
+
+impl<B: Body> Drop for Tee<B> {
+    fn drop(&mut self) {
+        // an empty body may never be polled, anything else ended early
+        let complete = self.inner.is_end_stream();
+        self.finish(complete);
+    }
+}
+
+impl<B> Body for Tee<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    self.captured.push(data);
+                }
+            }
+            Some(Err(_)) => self.finish(false),
+            None => self.finish(true),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A body streaming `chunks`, an `Err` fails it there.
+    struct Chunks(VecDeque<Result<&'static str, &'static str>>);
+
+    impl Body for Chunks {
+        type Data = Bytes;
+        type Error = BoxError;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+            Poll::Ready(self.0.pop_front().map(|chunk| {
+                chunk
+                    .map(|data| Frame::data(Bytes::from_static(data.as_bytes())))
+                    .map_err(Into::into)
+            }))
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.0.is_empty()
+        }
+    }
+
+    /// Helper to tee `body` and get what it captured once it is done.
+    fn tee<B: Body>(body: B) -> (Tee<B>, std::sync::mpsc::Receiver<Captured>) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tee = Tee::new(body, Captured::new(&hyper::HeaderMap::new(), 4), move |c| {
+            let _ = tx.send(c);
+        });
+        (tee, rx)
+    }
+
+    #[tokio::test]
+    async fn complete_once_the_body_ended() {
+        let (tee, rx) = tee(Chunks([Ok("abc"), Ok("def")].into()));
+        let body = tee.collect().await.unwrap().to_bytes();
+        assert_eq!(body, "abcdef");
+
+        let captured = rx.recv().unwrap();
+        assert!(captured.complete);
+        assert!(captured.truncated);
+        assert_eq!(captured.body_len, 6);
+        assert_eq!(captured.raw(), b"abcd");
+    }
+
+    #[tokio::test]
+    async fn incomplete_when_dropped_early() {
+        let (mut tee, rx) = tee(Chunks([Ok("abc"), Ok("def")].into()));
+        tee.frame().await.unwrap().unwrap();
+        drop(tee);
+
+        let captured = rx.recv().unwrap();
+        assert!(!captured.complete);
+        assert_eq!(captured.body_len, 3);
+    }
+
+    #[tokio::test]
+    async fn incomplete_on_error() {
+        let (tee, rx) = tee(Chunks([Ok("abc"), Err("connection reset")].into()));
+        assert!(tee.collect().await.is_err());
+        assert!(!rx.recv().unwrap().complete);
+    }
+
+    #[test]
+    fn empty_bodies_are_complete_unpolled() {
+        let (tee, rx) = tee(http_body_util::Empty::<Bytes>::new());
+        drop(tee);
+        assert!(rx.recv().unwrap().complete);
+    }
+}
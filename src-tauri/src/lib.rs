@@ -1,101 +1,254 @@
+mod accept;
+mod access_log;
+mod endpoint;
+mod entry;
+mod error_page;
+mod events;
+mod failover;
+mod har;
+mod headers;
 mod http_bridge;
+mod http_limits;
+mod identity;
+mod idle;
+mod inspect;
+mod lifecycle;
+mod log_files;
+mod logs;
+mod netmon;
+mod paths;
+mod peer_addr;
+mod pool;
+mod probe;
+mod profile;
+mod report;
+mod retry;
+mod share_link;
+mod supervisor;
 mod tcp_bridge;
 mod tcp_udp_bridge;
 mod udp_bridge;
+mod utc;
 use http_bridge::http_bridge;
 use std::collections::HashMap;
-use std::sync::{LazyLock, Mutex};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 use tauri_plugin_opener::OpenerExt;
 use tokio::sync::oneshot;
+use tracing::Instrument;
 
 static GRACEFUL: LazyLock<kulfi_utils::Graceful> = LazyLock::new(kulfi_utils::Graceful::new);
-static TASKLIST: LazyLock<Mutex<HashMap<String, tokio::sync::oneshot::Sender<()>>>> =
+static TASKLIST: LazyLock<Mutex<HashMap<String, Task>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
+/// A running bridge in `TASKLIST`, keyed by its URL.
+struct Task {
+    shutdown_tx: oneshot::Sender<()>,
+    /// The peers of the bridge and whether it holds connections to them.
+    activity: idle::Activity,
+    /// The profile whose endpoint the bridge dials from.
+    profile: String,
+}
+
+/// What the start commands report back.
+///
+/// `status` is `"Ok"` once the bridge is listening, `"Stopped"` if the call stopped a running
+/// bridge instead, or an error message. `reachability` is the result of the pre-flight check,
+/// present only when one was requested and the bridge started.
+#[derive(serde::Serialize)]
+struct StartResult {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reachability: Option<probe::Reachability>,
+}
+
+impl From<String> for StartResult {
+    fn from(status: String) -> Self {
+        StartResult {
+            status,
+            reachability: None,
+        }
+    }
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn browse(
     app_handle: tauri::AppHandle,
     port: u16,
     url: String,
     open_browser: bool,
-) -> String {
-    if let Some(task) = TASKLIST
-        .lock()
-        .expect("Unable to unlock task list")
-        .remove(&url)
-    {
-        match task.send(()) {
-            Ok(_) => {
-                println!("Stopped task for {url}");
-                return "Stopped".to_string();
-            }
-            Err(_) => {
-                println!("Error stopping task");
-                return format!("Error stopping task");
-            }
-        }
+    hints: Option<entry::AddrHints>,
+    preflight: Option<bool>,
+    identity: Option<String>,
+    fallbacks: Option<failover::Fallbacks>,
+    idle_secs: Option<u64>,
+    restart: Option<supervisor::RestartPolicy>,
+    access_log: Option<access_log::Format>,
+    inspect: Option<bool>,
+    har: Option<har::HarOptions>,
+    http_limits: Option<http_limits::HttpLimits>,
+    response_rewrite: Option<headers::ResponseRewrite>,
+    request_headers: Option<headers::RequestHeaders>,
+) -> StartResult {
+    if let Some(result) = try_stop_task(&url) {
+        return result.into();
     }
 
+    let profile = match resolve_profile(identity) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+
     let (id52, path) = match parse_url(&url) {
         Ok(v) => v,
         Err(e) => {
             tracing::error!(error = ?e, url, "Failed to parse URL");
-            eprintln!("Failed to parse URL: {e}");
-            return format!("Failed to parse URL: {}", e.to_string());
+            return format!("Failed to parse URL: {e}").into();
         }
     };
 
+    if let Err(e) = apply_hints(id52, hints) {
+        return e.into();
+    }
+
+    let fallbacks = fallbacks.unwrap_or_default();
+    let peers = match failover_peers(&url, id52, &fallbacks) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+
     let path = path.to_string();
+    let activity = idle::Activity::new(&url, &profile, peers.id52s(), idle_secs);
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let (startup_tx, startup_rx) = oneshot::channel();
 
-    tokio::spawn(http_bridge(
-        port,
-        Some(id52.to_string()),
-        GRACEFUL.clone(),
-        shutdown_rx,
-        startup_tx,
-        move |port: u16| {
-            if open_browser {
-                let url = format!("http://127.0.0.1:{port}/{path}");
-                app_handle
-                    .opener()
-                    .open_url(url, None::<&str>)
-                    .map_err(Into::into)
-            } else {
-                Ok(())
-            }
+    let host = request_headers.as_ref().and_then(|h| h.host.as_deref());
+    if let Err(e) = headers::set_rewrite(&url, response_rewrite, host) {
+        return format!("Invalid response rewrite: {e}").into();
+    }
+    if let Err(e) = headers::set_request_headers(&url, request_headers) {
+        return format!("Invalid request headers: {e}").into();
+    }
+
+    har::stop(&url);
+    if let Some(har) = &har
+        && let Err(e) = har::start(&url, har)
+    {
+        tracing::error!(url, "failed to start HAR session: {e:?}");
+        return format!("Failed to open HAR file: {e}").into();
+    }
+
+    http_limits::set(&url, http_limits.unwrap_or_default());
+    access_log::start(&url, access_log);
+    let recording = har
+        .as_ref()
+        .is_some_and(|har| har.mode == har::Mode::Record);
+    // recordings need the whole bodies, the inspector only shows their beginning
+    inspect::start(
+        &url,
+        if recording {
+            Some(har::BODY_LIMIT)
+        } else {
+            inspect.unwrap_or(false).then_some(inspect::BODY_LIMIT)
         },
-    ));
+    );
 
-    // Wait for startup result
-    match startup_rx.await {
-        Ok(Ok(())) => {
-            TASKLIST
-                .lock()
-                .expect("Unable to unlock task list")
-                .insert(url, shutdown_tx);
-            "Ok".to_string()
-        }
-        Ok(Err(e)) => e,
-        Err(_) => "Failed to start HTTP bridge: channel closed".to_string(),
+    // restarts bind the port the first start got and do not open the browser again
+    let bound_port = Arc::new(AtomicU16::new(port));
+    let mut open_browser = open_browser;
+    let span = tracing::info_span!("bridge", %url, protocol = "http", peer = activity.id52());
+    tokio::spawn(
+        supervisor::supervise(
+            url.clone(),
+            restart.unwrap_or_default(),
+            shutdown_rx,
+            startup_tx,
+            {
+                let (peers, profile, activity) = (peers.clone(), profile.clone(), activity.clone());
+                move |shutdown_rx, startup_tx| {
+                    let open_browser = std::mem::take(&mut open_browser);
+                    let (app_handle, path, bound_port) =
+                        (app_handle.clone(), path.clone(), bound_port.clone());
+                    http_bridge(
+                        bound_port.load(Ordering::SeqCst),
+                        Some(peers.clone()),
+                        profile.clone(),
+                        activity.clone(),
+                        GRACEFUL.clone(),
+                        shutdown_rx,
+                        startup_tx,
+                        move |port: u16| {
+                            bound_port.store(port, Ordering::SeqCst);
+                            if open_browser {
+                                let url = format!("http://127.0.0.1:{port}/{path}");
+                                app_handle
+                                    .opener()
+                                    .open_url(url, None::<&str>)
+                                    .map_err(Into::into)
+                            } else {
+                                Ok(())
+                            }
+                        },
+                    )
+                }
+            },
+        )
+        .instrument(span),
+    );
+
+    let result = wait_for_startup(
+        url,
+        activity,
+        &profile,
+        "HTTP",
+        shutdown_tx,
+        startup_rx,
+        preflight,
+    )
+    .await;
+    if result.status == "Ok" {
+        failover::watch(
+            &peers,
+            &profile,
+            fallbacks.failback(),
+            fallbacks.health_check(),
+        );
     }
+    result
 }
 
 /// Helper to stop an existing task by URL, returning Some(result) if stopped.
 fn try_stop_task(url: &str) -> Option<String> {
+    stop_task(url, None)
+}
+
+/// Stops an existing task by URL and reports it stopped with `error`, the reason it failed.
+fn stop_task(url: &str, error: Option<String>) -> Option<String> {
     if let Some(task) = TASKLIST
         .lock()
         .expect("Unable to unlock task list")
         .remove(url)
     {
-        match task.send(()) {
+        for peer in task.activity.peers() {
+            paths::unwatch(peer);
+        }
+        task.activity.stop();
+        retry::forget(url);
+        failover::unwatch(url);
+        access_log::stop(url);
+        har::stop(url);
+        http_limits::forget(url);
+        headers::forget(url);
+        lifecycle::emit(url, lifecycle::BridgeState::Stopped, error);
+
+        match task.shutdown_tx.send(()) {
             Ok(_) => {
-                println!("Stopped task for {url}");
+                tracing::info!(url, "stopped task");
                 Some("Stopped".to_string())
             }
             Err(_) => {
-                println!("Error stopping task");
+                tracing::error!(url, "error stopping task");
                 Some("Error stopping task".to_string())
             }
         }
@@ -104,124 +257,455 @@ fn try_stop_task(url: &str) -> Option<String> {
     }
 }
 
+/// Helper to wait for a bridge to bind, register it in the task list and, if requested, dial the
+/// peer to report whether it is reachable.
+///
+async fn wait_for_startup(
+    url: String,
+    activity: idle::Activity,
+    profile: &str,
+    kind: &str,
+    shutdown_tx: oneshot::Sender<()>,
+    startup_rx: oneshot::Receiver<Result<(), String>>,
+    preflight: Option<bool>,
+) -> StartResult {
+    let id52 = activity.id52();
+    match startup_rx.await {
+        Ok(Ok(())) => {
+            lifecycle::emit(&url, lifecycle::BridgeState::Running, None);
+            retry::reset(&url);
+            supervisor::reset(&url);
+            TASKLIST.lock().expect("Unable to unlock task list").insert(
+                url,
+                Task {
+                    shutdown_tx,
+                    activity: activity.clone(),
+                    profile: profile.to_string(),
+                },
+            );
+            activity.start();
+            // every peer is watched, failover may move the bridge to any of them
+            for peer in activity.peers() {
+                paths::watch(peer, profile);
+            }
+        }
+        Ok(Err(e)) => return e.into(),
+        Err(_) => return format!("Failed to start {kind} bridge: channel closed").into(),
+    }
+
+    let reachability = match preflight {
+        Some(true) => Some(probe::dial(id52, profile, probe::PREFLIGHT_TIMEOUT).await),
+        _ => None,
+    };
+
+    StartResult {
+        status: "Ok".to_string(),
+        reachability,
+    }
+}
+
 /// Helper to extract id52 from a kulfi:// URL, returning an error string on failure.
 fn extract_id52(url: &str) -> Result<String, String> {
     match parse_url(url) {
         Ok((id52, _)) => Ok(id52.to_string()),
         Err(e) => {
             tracing::error!(error = ?e, url, "Failed to parse URL");
-            eprintln!("Failed to parse URL: {e}");
             Err(format!("Failed to parse URL: {e}"))
         }
     }
 }
 
+/// Helper to build the peers of a bridge from its primary id52 and the URLs of its fallbacks.
+fn failover_peers(
+    url: &str,
+    id52: &str,
+    fallbacks: &failover::Fallbacks,
+) -> Result<failover::Peers, String> {
+    let mut id52s = vec![id52.to_string()];
+    for fallback in &fallbacks.urls {
+        let fallback = extract_id52(fallback)?;
+        if !id52s.contains(&fallback) {
+            id52s.push(fallback);
+        }
+    }
+    Ok(failover::Peers::new(url, id52s, fallbacks.strategy))
+}
+
+/// Helper to pick the profile a bridge dials from, the active one unless the entry names one.
+fn resolve_profile(identity: Option<String>) -> Result<String, String> {
+    match identity.filter(|i| !i.is_empty()) {
+        Some(profile) if profile::exists(&profile) => Ok(profile),
+        Some(profile) => Err(format!(
+            "Unknown identity: profile {profile} does not exist"
+        )),
+        None => Ok(profile::active()),
+    }
+}
+
+/// Helper to feed the address hints of an entry to the endpoint, returning an error string on failure.
+fn apply_hints(id52: &str, hints: Option<entry::AddrHints>) -> Result<(), String> {
+    let Some(hints) = hints.filter(|h| !h.is_empty()) else {
+        return Ok(());
+    };
+
+    peer_addr::apply_hints(id52, &hints).map_err(|e| {
+        tracing::error!(error = ?e, id52, "Failed to apply address hints");
+        format!("Invalid address hints: {e}")
+    })
+}
+
 #[tauri::command]
-async fn tcp_connect(port: u16, url: String) -> String {
+#[allow(clippy::too_many_arguments)]
+async fn tcp_connect(
+    port: u16,
+    url: String,
+    hints: Option<entry::AddrHints>,
+    preflight: Option<bool>,
+    identity: Option<String>,
+    retry: Option<retry::RetryPolicy>,
+    fallbacks: Option<failover::Fallbacks>,
+    idle_secs: Option<u64>,
+    restart: Option<supervisor::RestartPolicy>,
+) -> StartResult {
     if let Some(result) = try_stop_task(&url) {
-        return result;
+        return result.into();
     }
 
+    let profile = match resolve_profile(identity) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+
     let id52 = match extract_id52(&url) {
         Ok(v) => v,
-        Err(e) => return e,
+        Err(e) => return e.into(),
+    };
+
+    if let Err(e) = apply_hints(&id52, hints) {
+        return e.into();
+    }
+
+    let fallbacks = fallbacks.unwrap_or_default();
+    let peers = match failover_peers(&url, &id52, &fallbacks) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
     };
 
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let (startup_tx, startup_rx) = oneshot::channel();
 
-    tokio::spawn(tcp_bridge::tcp_bridge(
-        port,
-        id52,
-        GRACEFUL.clone(),
-        shutdown_rx,
-        startup_tx,
-    ));
+    let activity = idle::Activity::new(&url, &profile, peers.id52s(), idle_secs);
+    // restarts bind the port the first start got
+    let bound_port = Arc::new(AtomicU16::new(port));
+    let span = tracing::info_span!("bridge", %url, protocol = "tcp", peer = activity.id52());
+    tokio::spawn(
+        supervisor::supervise(
+            url.clone(),
+            restart.unwrap_or_default(),
+            shutdown_rx,
+            startup_tx,
+            {
+                let (peers, profile, activity) = (peers.clone(), profile.clone(), activity.clone());
+                let dialer = retry::Dialer::new(&url, retry);
+                move |shutdown_rx, startup_tx| {
+                    tcp_bridge::tcp_bridge(
+                        bound_port.clone(),
+                        peers.clone(),
+                        profile.clone(),
+                        dialer.clone(),
+                        activity.clone(),
+                        GRACEFUL.clone(),
+                        shutdown_rx,
+                        startup_tx,
+                    )
+                }
+            },
+        )
+        .instrument(span),
+    );
 
-    // Wait for startup result
-    match startup_rx.await {
-        Ok(Ok(())) => {
-            TASKLIST
-                .lock()
-                .expect("Unable to unlock task list")
-                .insert(url, shutdown_tx);
-            "Ok".to_string()
-        }
-        Ok(Err(e)) => e,
-        Err(_) => "Failed to start TCP bridge: channel closed".to_string(),
+    let result = wait_for_startup(
+        url,
+        activity,
+        &profile,
+        "TCP",
+        shutdown_tx,
+        startup_rx,
+        preflight,
+    )
+    .await;
+    if result.status == "Ok" {
+        failover::watch(
+            &peers,
+            &profile,
+            fallbacks.failback(),
+            fallbacks.health_check(),
+        );
     }
+    result
 }
 
 #[tauri::command]
-async fn udp_connect(port: u16, url: String) -> String {
+#[allow(clippy::too_many_arguments)]
+async fn udp_connect(
+    port: u16,
+    url: String,
+    hints: Option<entry::AddrHints>,
+    preflight: Option<bool>,
+    identity: Option<String>,
+    retry: Option<retry::RetryPolicy>,
+    idle_secs: Option<u64>,
+    restart: Option<supervisor::RestartPolicy>,
+) -> StartResult {
     if let Some(result) = try_stop_task(&url) {
-        return result;
+        return result.into();
     }
 
+    let profile = match resolve_profile(identity) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+
     let id52 = match extract_id52(&url) {
         Ok(v) => v,
-        Err(e) => return e,
+        Err(e) => return e.into(),
     };
 
+    if let Err(e) = apply_hints(&id52, hints) {
+        return e.into();
+    }
+
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let (startup_tx, startup_rx) = oneshot::channel();
 
-    tokio::spawn(udp_bridge::udp_bridge(
-        port,
-        id52,
-        GRACEFUL.clone(),
-        shutdown_rx,
-        startup_tx,
-    ));
+    let activity = idle::Activity::new(&url, &profile, std::slice::from_ref(&id52), idle_secs);
+    // restarts bind the port the first start got
+    let bound_port = Arc::new(AtomicU16::new(port));
+    let span = tracing::info_span!("bridge", %url, protocol = "udp", peer = activity.id52());
+    tokio::spawn(
+        supervisor::supervise(
+            url.clone(),
+            restart.unwrap_or_default(),
+            shutdown_rx,
+            startup_tx,
+            {
+                let (id52, profile, activity) = (id52.clone(), profile.clone(), activity.clone());
+                let dialer = retry::Dialer::new(&url, retry);
+                move |shutdown_rx, startup_tx| {
+                    udp_bridge::udp_bridge(
+                        bound_port.clone(),
+                        id52.clone(),
+                        profile.clone(),
+                        dialer.clone(),
+                        activity.clone(),
+                        GRACEFUL.clone(),
+                        shutdown_rx,
+                        startup_tx,
+                    )
+                }
+            },
+        )
+        .instrument(span),
+    );
 
-    // Wait for startup result
-    match startup_rx.await {
-        Ok(Ok(())) => {
-            TASKLIST
-                .lock()
-                .expect("Unable to unlock task list")
-                .insert(url, shutdown_tx);
-            "Ok".to_string()
-        }
-        Ok(Err(e)) => e,
-        Err(_) => "Failed to start UDP bridge: channel closed".to_string(),
-    }
+    wait_for_startup(
+        url,
+        activity,
+        &profile,
+        "UDP",
+        shutdown_tx,
+        startup_rx,
+        preflight,
+    )
+    .await
 }
 
 #[tauri::command]
-async fn tcp_udp_connect(port: u16, url: String) -> String {
+#[allow(clippy::too_many_arguments)]
+async fn tcp_udp_connect(
+    port: u16,
+    url: String,
+    hints: Option<entry::AddrHints>,
+    preflight: Option<bool>,
+    identity: Option<String>,
+    retry: Option<retry::RetryPolicy>,
+    idle_secs: Option<u64>,
+    restart: Option<supervisor::RestartPolicy>,
+) -> StartResult {
     if let Some(result) = try_stop_task(&url) {
-        return result;
+        return result.into();
     }
 
+    let profile = match resolve_profile(identity) {
+        Ok(v) => v,
+        Err(e) => return e.into(),
+    };
+
     let id52 = match extract_id52(&url) {
         Ok(v) => v,
-        Err(e) => return e,
+        Err(e) => return e.into(),
     };
 
+    if let Err(e) = apply_hints(&id52, hints) {
+        return e.into();
+    }
+
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
     let (startup_tx, startup_rx) = oneshot::channel();
 
-    tokio::spawn(tcp_udp_bridge::tcp_udp_bridge(
-        port,
-        id52,
-        GRACEFUL.clone(),
-        shutdown_rx,
-        startup_tx,
-    ));
+    let activity = idle::Activity::new(&url, &profile, std::slice::from_ref(&id52), idle_secs);
+    // restarts bind the port the first start got
+    let bound_port = Arc::new(AtomicU16::new(port));
+    let span = tracing::info_span!("bridge", %url, protocol = "tcp+udp", peer = activity.id52());
+    tokio::spawn(
+        supervisor::supervise(
+            url.clone(),
+            restart.unwrap_or_default(),
+            shutdown_rx,
+            startup_tx,
+            {
+                let (id52, profile, activity) = (id52.clone(), profile.clone(), activity.clone());
+                let dialer = retry::Dialer::new(&url, retry);
+                move |shutdown_rx, startup_tx| {
+                    tcp_udp_bridge::tcp_udp_bridge(
+                        bound_port.clone(),
+                        id52.clone(),
+                        profile.clone(),
+                        dialer.clone(),
+                        activity.clone(),
+                        GRACEFUL.clone(),
+                        shutdown_rx,
+                        startup_tx,
+                    )
+                }
+            },
+        )
+        .instrument(span),
+    );
 
-    // Wait for startup result
-    match startup_rx.await {
-        Ok(Ok(())) => {
-            TASKLIST
-                .lock()
-                .expect("Unable to unlock task list")
-                .insert(url, shutdown_tx);
-            "Ok".to_string()
-        }
-        Ok(Err(e)) => e,
-        Err(_) => "Failed to start TCP+UDP bridge: channel closed".to_string(),
-    }
+    wait_for_startup(
+        url,
+        activity,
+        &profile,
+        "TCP+UDP",
+        shutdown_tx,
+        startup_rx,
+        preflight,
+    )
+    .await
+}
+
+/// Measures the round trip time to the peer of `url` over a pooled connection, like the bridges
+/// use, and optionally the throughput of a timed transfer against one of its services.
+#[tauri::command]
+async fn probe_peer(
+    url: String,
+    hints: Option<entry::AddrHints>,
+    transfer: Option<probe::TransferTest>,
+    identity: Option<String>,
+) -> Result<probe::ProbeReport, String> {
+    let profile = resolve_profile(identity)?;
+    let (id52, path) = parse_url(&url).map_err(|e| format!("Failed to parse URL: {e}"))?;
+    apply_hints(id52, hints)?;
+
+    probe::probe(id52, &profile, transfer, path)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = ?e, url, "Failed to probe peer");
+            format!("Failed to probe {id52}: {e}")
+        })
+}
+
+/// The current connection path of every peer with a running bridge, updates are sent as
+/// `peer-path` events.
+#[tauri::command]
+fn peer_paths() -> Vec<paths::PeerPath> {
+    paths::current()
+}
+
+/// Peer stream counters of every running bridge by URL, retries and final failures are also sent
+/// as `peer-stream` events.
+#[tauri::command]
+fn stream_metrics() -> HashMap<String, retry::StreamMetrics> {
+    retry::metrics()
+}
+
+/// The active peer of every running bridge with fallbacks, switches are sent as `active-peer`
+/// events.
+#[tauri::command]
+fn active_peers() -> Vec<failover::ActivePeer> {
+    failover::current()
+}
+
+/// How often each bridge was restarted by its supervisor since it was started, and why.
+#[tauri::command]
+fn bridge_restarts() -> HashMap<String, supervisor::RestartStats> {
+    supervisor::stats()
+}
+
+/// The last requests of the HTTP bridge of `url`, oldest first, new ones are sent as
+/// `access-log` events.
+#[tauri::command]
+fn access_log(url: String) -> Vec<access_log::AccessEntry> {
+    access_log::entries(&url)
+}
+
+/// The captured exchanges of the inspected HTTP bridge of `url`, oldest first, new ones and
+/// their progress are sent as `http-exchange` events.
+#[tauri::command]
+fn http_exchanges(url: String) -> Vec<inspect::Exchange> {
+    inspect::exchanges(&url)
+}
+
+/// The start of the bodies of exchange `id` of the inspected HTTP bridge of `url`, left out of
+/// `http-exchange` events.
+#[tauri::command]
+fn http_exchange_bodies(url: String, id: u64) -> Result<inspect::Bodies, String> {
+    inspect::bodies(&url, id).map_err(|e| format!("Failed to get bodies: {e}"))
+}
+
+/// Sends a captured request of the running bridge of `url` again, returns the new exchange's id.
+#[tauri::command]
+async fn replay_request(url: String, id: u64) -> Result<u64, String> {
+    let profile = TASKLIST
+        .lock()
+        .expect("Unable to unlock task list")
+        .get(&url)
+        .map(|task| task.profile.clone())
+        .ok_or_else(|| format!("{url} is not running"))?;
+    http_bridge::replay(url, id, &profile, GRACEFUL.clone())
+        .await
+        .map_err(|e| format!("Failed to replay request: {e}"))
+}
+
+/// The buffered log entries, oldest first, new ones are sent as `log` events.
+#[tauri::command]
+fn logs() -> Vec<logs::LogEntry> {
+    logs::entries()
+}
+
+#[tauri::command]
+fn clear_logs() {
+    logs::clear()
+}
+
+#[tauri::command]
+fn log_level() -> String {
+    logs::level()
+}
+
+#[tauri::command]
+fn set_log_level(level: String) -> Result<(), String> {
+    logs::set_level(&level).map_err(|e| format!("Failed to set log level: {e}"))
+}
+
+/// Writes a bug report archive with the logs, the configuration and a summary of the app's
+/// state, returns its path.
+#[tauri::command]
+fn export_bug_report(app_handle: tauri::AppHandle) -> Result<String, String> {
+    report::export(&app_handle)
+        .map(|path| path.display().to_string())
+        .map_err(|e| format!("Failed to export bug report: {e}"))
 }
 
 #[tauri::command]
@@ -232,6 +716,161 @@ fn status(url: String) -> bool {
         .contains_key(&url)
 }
 
+/// Encodes every URL entry of `item` as a share link, one link per line.
+#[tauri::command]
+fn share_item(item: entry::SharedItem) -> Result<String, String> {
+    item.urls
+        .iter()
+        .map(|entry| share_link::to_share_link(&item.name, entry))
+        .collect::<eyre::Result<Vec<_>>>()
+        .map(|links| links.join("\n"))
+        .map_err(|e| format!("Failed to create share link: {e}"))
+}
+
+/// Parses share links, one per line, into ready-to-run items.
+#[tauri::command]
+fn import_share_links(text: String) -> Result<Vec<entry::SharedItem>, String> {
+    share_link::import_share_links(&text).map_err(|e| format!("Failed to import share link: {e}"))
+}
+
+/// The network settings of the active profile.
+#[tauri::command]
+fn network_settings() -> endpoint::NetworkSettings {
+    endpoint::settings(&profile::active())
+}
+
+/// Applies and persists new network settings for the active profile, its endpoint is rebuilt if
+/// it is in use.
+#[tauri::command]
+async fn set_network_settings(
+    app_handle: tauri::AppHandle,
+    settings: endpoint::NetworkSettings,
+) -> Result<(), String> {
+    let profile = profile::active();
+    endpoint::apply_settings(&profile, settings.clone())
+        .await
+        .map_err(|e| format!("Failed to apply network settings: {e}"))?;
+    endpoint::save_settings(&app_handle, &profile, &settings).map_err(|e| {
+        tracing::error!(error = ?e, "Failed to save network settings");
+        format!("Failed to save network settings: {e}")
+    })
+}
+
+/// Our own id52 in the active profile, the identity peers see when a bridge dials them.
+#[tauri::command]
+fn identity() -> Result<String, String> {
+    let profile = profile::active();
+    if let Some(error) = identity::load_error(&profile) {
+        return Err(format!(
+            "The identity of profile {profile} could not be loaded: {error}"
+        ));
+    }
+    identity::id52(&profile).ok_or_else(|| format!("Profile {profile} has no identity"))
+}
+
+/// Switches the active profile to a freshly generated secret key, returning the new id52.
+#[tauri::command]
+async fn rotate_identity(app_handle: tauri::AppHandle) -> Result<String, String> {
+    identity::rotate(&app_handle, &profile::active())
+        .await
+        .map_err(|e| {
+            tracing::error!(error = ?e, "Failed to rotate identity");
+            format!("Failed to rotate identity: {e}")
+        })
+}
+
+/// Returns the secret key of the active profile encrypted with `password`.
+#[tauri::command]
+fn export_identity(password: String) -> Result<String, String> {
+    identity::export(&profile::active(), &password)
+        .map_err(|e| format!("Failed to export identity: {e}"))
+}
+
+/// Switches the active profile to an exported or plain secret key, returning its id52.
+#[tauri::command]
+async fn import_identity(
+    app_handle: tauri::AppHandle,
+    key: String,
+    password: Option<String>,
+) -> Result<String, String> {
+    identity::import(&app_handle, &profile::active(), &key, password.as_deref())
+        .await
+        .map_err(|e| format!("Failed to import identity: {e}"))
+}
+
+#[tauri::command]
+fn profiles() -> Vec<profile::ProfileInfo> {
+    profile::list()
+}
+
+/// Creates a profile with a fresh identity, without switching to it.
+#[tauri::command]
+fn create_profile(
+    app_handle: tauri::AppHandle,
+    name: String,
+) -> Result<profile::ProfileInfo, String> {
+    profile::create(&app_handle, &name).map_err(|e| format!("Failed to create profile: {e}"))
+}
+
+#[tauri::command]
+async fn delete_profile(app_handle: tauri::AppHandle, name: String) -> Result<(), String> {
+    profile::deletable(&name).map_err(|e| format!("Failed to delete profile: {e}"))?;
+    stop_profile_tasks(&name);
+    profile::delete(&app_handle, &name)
+        .await
+        .map_err(|e| format!("Failed to delete profile: {e}"))
+}
+
+/// Stops the bridges of the active profile and makes `name` the active profile. Bridges of other
+/// profiles, started from entries that name their own identity, keep running.
+#[tauri::command]
+async fn switch_profile(
+    app_handle: tauri::AppHandle,
+    name: String,
+) -> Result<profile::ProfileInfo, String> {
+    if !profile::exists(&name) {
+        return Err(format!("Profile {name} does not exist"));
+    }
+
+    stop_profile_tasks(&profile::active());
+    profile::switch(&app_handle, &name).await.map_err(|e| {
+        tracing::error!(error = ?e, name, "Failed to switch profile");
+        format!("Failed to switch profile: {e}")
+    })
+}
+
+/// Helper to list the running bridges that are not idle as `(url, id52, profile)`.
+fn running_tasks() -> Vec<(String, String, String)> {
+    TASKLIST
+        .lock()
+        .expect("Unable to unlock task list")
+        .iter()
+        .filter(|(_, task)| !task.activity.is_idle())
+        .map(|(url, task)| {
+            (
+                url.clone(),
+                task.activity.id52().to_string(),
+                task.profile.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Stops the bridges dialing from `profile`, before switching away from it or deleting it.
+/// Bridges dialing from other profiles keep running.
+fn stop_profile_tasks(profile: &str) {
+    let urls: Vec<String> = TASKLIST
+        .lock()
+        .expect("Unable to unlock task list")
+        .iter()
+        .filter(|(_, task)| task.profile == profile)
+        .map(|(url, _)| url.clone())
+        .collect();
+    for url in urls {
+        try_stop_task(&url);
+    }
+}
+
 /// This function extracts the id52 and the path from the URL
 ///
 /// the path is the part after the first / in the URL
@@ -254,31 +893,112 @@ fn parse_url(url: &str) -> eyre::Result<(&str, &str)> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    logs::init();
+
     #[cfg(mobile)]
     let builder = tauri::Builder::default()
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![browse, tcp_connect, udp_connect, tcp_udp_connect, status]);
+        .invoke_handler(tauri::generate_handler![
+            browse,
+            tcp_connect,
+            udp_connect,
+            tcp_udp_connect,
+            status,
+            share_item,
+            import_share_links,
+            probe_peer,
+            peer_paths,
+            stream_metrics,
+            active_peers,
+            bridge_restarts,
+            logs,
+            clear_logs,
+            log_level,
+            set_log_level,
+            access_log,
+            http_exchanges,
+            http_exchange_bodies,
+            replay_request,
+            export_bug_report,
+            network_settings,
+            set_network_settings,
+            identity,
+            rotate_identity,
+            export_identity,
+            import_identity,
+            profiles,
+            create_profile,
+            delete_profile,
+            switch_profile
+        ]);
 
     #[cfg(desktop)]
     let builder = tauri::Builder::default()
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![browse, tcp_connect, udp_connect, tcp_udp_connect, status]);
+        .invoke_handler(tauri::generate_handler![
+            browse,
+            tcp_connect,
+            udp_connect,
+            tcp_udp_connect,
+            status,
+            share_item,
+            import_share_links,
+            probe_peer,
+            peer_paths,
+            stream_metrics,
+            active_peers,
+            bridge_restarts,
+            logs,
+            clear_logs,
+            log_level,
+            set_log_level,
+            access_log,
+            http_exchanges,
+            http_exchange_bodies,
+            replay_request,
+            export_bug_report,
+            network_settings,
+            set_network_settings,
+            identity,
+            rotate_identity,
+            export_identity,
+            import_identity,
+            profiles,
+            create_profile,
+            delete_profile,
+            switch_profile
+        ]);
 
-    #[cfg(mobile)]
     let builder = builder.setup(|app| {
-        use tauri_plugin_notification::NotificationExt;
-        app.notification()
-            .builder()
-            .title("Malai client")
-            .body("I guess you need this to keep the app alive on android.")
-            .ongoing()
-            .show()
-            .unwrap();
+        events::init(app.handle().clone());
+        if let Err(e) = log_files::init(app.handle()) {
+            tracing::error!("failed to open log files: {e:?}");
+        }
+        if let Err(e) = access_log::init(app.handle()) {
+            tracing::error!("failed to create the access log directory: {e:?}");
+        }
+        if let Err(e) = har::init(app.handle()) {
+            tracing::error!("failed to create the HAR directory: {e:?}");
+        }
+        profile::load(app.handle());
+        tauri::async_runtime::spawn(netmon::monitor());
+
+        #[cfg(mobile)]
+        {
+            use tauri_plugin_notification::NotificationExt;
+            app.notification()
+                .builder()
+                .title("Malai client")
+                .body("I guess you need this to keep the app alive on android.")
+                .ongoing()
+                .show()
+                .unwrap();
+        }
         Ok(())
     });
 
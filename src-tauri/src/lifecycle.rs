@@ -0,0 +1,54 @@
+/// State of a running bridge, sent to the frontend as `bridge-state` events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BridgeState {
+    Running,
+    /// An on-demand bridge that listens but holds no connection to its peer until a client
+    /// connects.
+    Idle,
+    /// The network changed and the peer has not answered on the new network yet.
+    Reconnecting,
+    /// The bridge failed and is started again after a backoff, the error says why it failed.
+    Restarting,
+    Stopped,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeEvent {
+    pub url: String,
+    pub state: BridgeState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn emit(url: &str, state: BridgeState, error: Option<String>) {
+    tracing::debug!(url, ?state, ?error, "bridge state changed");
+    crate::events::emit(
+        "bridge-state",
+        BridgeEvent {
+            url: url.to_string(),
+            state,
+            error,
+        },
+    );
+}
+
+/// A problem of a running bridge that does not stop it, sent to the frontend as
+/// `bridge-warning` events. A `None` warning clears the previous one.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeWarning {
+    pub url: String,
+    pub warning: Option<String>,
+}
+
+pub fn warn(url: &str, warning: Option<String>) {
+    crate::events::emit(
+        "bridge-warning",
+        BridgeWarning {
+            url: url.to_string(),
+            warning,
+        },
+    );
+}
@@ -0,0 +1,155 @@
+use crate::logs::LogEntry;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Directory in the app data dir holding the log files.
+const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "malai-client.log";
+/// The current file is rotated once it grows past this.
+const MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+/// Rotated files kept besides the current one, `malai-client.log.1` being the newest.
+const MAX_ROTATED: usize = 4;
+/// Rotated files older than this are deleted whatever their number.
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+struct Sink {
+    dir: PathBuf,
+    /// Closed while rotating, as open files can not be renamed on Windows.
+    file: Option<File>,
+    size: u64,
+}
+
+/// Unset until `init`, entries logged before are written out by it from the in-memory buffer.
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+
+/// Starts writing log entries to rotating files in the app data dir, called once from `setup`.
+pub fn init(app_handle: &tauri::AppHandle) -> eyre::Result<()> {
+    use tauri::Manager;
+
+    let dir = app_handle.path().app_data_dir()?.join(LOG_DIR);
+    std::fs::create_dir_all(&dir)?;
+    prune(&dir);
+
+    let (file, size) = open(&dir)?;
+    let mut sink = Sink {
+        dir,
+        file: Some(file),
+        size,
+    };
+    for entry in crate::logs::entries() {
+        sink.write(&entry);
+    }
+    *SINK.lock().expect("Unable to unlock log file") = Some(sink);
+    Ok(())
+}
+
+/// Appends `entry` to the current log file, called for every entry by `crate::logs`.
+pub fn write(entry: &LogEntry) {
+    if let Some(sink) = SINK.lock().expect("Unable to unlock log file").as_mut() {
+        sink.write(entry);
+    }
+}
+
+/// The log files, oldest first.
+pub fn files() -> Vec<PathBuf> {
+    let sink = SINK.lock().expect("Unable to unlock log file");
+    let Some(sink) = sink.as_ref() else {
+        return vec![];
+    };
+
+    (1..=MAX_ROTATED)
+        .rev()
+        .map(|n| rotated(&sink.dir, n))
+        .chain([sink.dir.join(LOG_FILE)])
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Opens the current log file for appending, along with its size.
+fn open(dir: &Path) -> eyre::Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LOG_FILE))?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn rotated(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("{LOG_FILE}.{n}"))
+}
+
+impl Sink {
+    fn write(&mut self, entry: &LogEntry) {
+        let mut line = format!(
+            "{} {:>5} {}: {}",
+            crate::utc::Utc::from_unix_ms(entry.time).iso8601(),
+            entry.level,
+            entry.target,
+            entry.message
+        );
+        for (key, value) in &entry.fields {
+            line.push_str(&format!(" {key}={value}"));
+        }
+        line.push('\n');
+
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+        // logging here would log itself again, so failures go to stderr only
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            eprintln!("Failed to write log file: {e}");
+            return;
+        }
+        self.size += line.len() as u64;
+
+        if self.size > MAX_FILE_SIZE
+            && let Err(e) = self.rotate()
+        {
+            eprintln!("Failed to rotate log file: {e}");
+            // keep writing to the current file rather than nowhere
+            if self.file.is_none()
+                && let Ok((file, size)) = open(&self.dir)
+            {
+                self.file = Some(file);
+                self.size = size;
+            }
+        }
+    }
+
+    fn rotate(&mut self) -> eyre::Result<()> {
+        self.file = None;
+        let _ = std::fs::remove_file(rotated(&self.dir, MAX_ROTATED));
+        for n in (1..MAX_ROTATED).rev() {
+            let from = rotated(&self.dir, n);
+            if from.exists() {
+                std::fs::rename(from, rotated(&self.dir, n + 1))?;
+            }
+        }
+        std::fs::rename(self.dir.join(LOG_FILE), rotated(&self.dir, 1))?;
+
+        let (file, size) = open(&self.dir)?;
+        self.file = Some(file);
+        self.size = size;
+        prune(&self.dir);
+        Ok(())
+    }
+}
+
+/// Deletes the rotated files past `MAX_AGE`.
+fn prune(dir: &Path) {
+    for n in 1..=MAX_ROTATED {
+        let path = rotated(dir, n);
+        let expired = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > MAX_AGE);
+        if expired {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::SystemTime;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry, reload};
+
+/// How many entries the log viewer can scroll back to.
+const CAPACITY: usize = 2_000;
+
+/// A log line for the in-app viewer, sent to the frontend as `log` events.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogEntry {
+    /// Unix time, in milliseconds.
+    pub time: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    /// The event's fields and those of the spans it happened in, like a bridge's `url`.
+    pub fields: BTreeMap<String, String>,
+}
+
+static ENTRIES: LazyLock<Mutex<VecDeque<LogEntry>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(CAPACITY)));
+
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+thread_local! {
+    /// Set while an entry is emitted, so a failing emit that logs does not log itself again.
+    static EMITTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs the global subscriber, logging to stderr and to the in-app viewer, called once
+/// from `run`.
+pub fn init() {
+    let (filter, handle) = reload::Layer::new(LevelFilter::INFO);
+    let _ = LEVEL.set(handle);
+    if let Err(e) = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(ViewerLayer)
+        .try_init()
+    {
+        eprintln!("Failed to install the log subscriber: {e}");
+    }
+}
+
+pub fn entries() -> Vec<LogEntry> {
+    ENTRIES
+        .lock()
+        .expect("Unable to unlock log entries")
+        .iter()
+        .cloned()
+        .collect()
+}
+
+pub fn clear() {
+    ENTRIES
+        .lock()
+        .expect("Unable to unlock log entries")
+        .clear();
+}
+
+pub fn level() -> String {
+    LEVEL
+        .get()
+        .and_then(|handle| handle.clone_current())
+        .unwrap_or(LevelFilter::INFO)
+        .to_string()
+        .to_lowercase()
+}
+
+/// Changes which levels are logged, one of `off`, `error`, `warn`, `info`, `debug` or `trace`.
+pub fn set_level(level: &str) -> eyre::Result<()> {
+    let level: LevelFilter = level
+        .parse()
+        .map_err(|_| eyre::anyhow!("unknown log level {level}"))?;
+    LEVEL
+        .get()
+        .ok_or_else(|| eyre::anyhow!("logging is not initialized"))?
+        .reload(level)?;
+    tracing::info!(%level, "log level changed");
+    Ok(())
+}
+
+/// Collects fields as strings, `message` is the event's formatted message.
+#[derive(Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+/// Records every event into `ENTRIES` and emits it to the frontend.
+struct ViewerLayer;
+
+impl<S> Layer<S> for ViewerLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if EMITTING.get() {
+            return;
+        }
+
+        // outer spans first, so the innermost value of a field wins
+        let mut fields = Fields::default();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<Fields>() {
+                    fields.0.extend(span_fields.0.clone());
+                }
+            }
+        }
+        event.record(&mut fields);
+
+        let metadata = event.metadata();
+        let entry = LogEntry {
+            time: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: fields.0.remove("message").unwrap_or_default(),
+            fields: fields.0,
+        };
+
+        {
+            let mut entries = ENTRIES.lock().expect("Unable to unlock log entries");
+            if entries.len() == CAPACITY {
+                entries.pop_front();
+            }
+            entries.push_back(entry.clone());
+        }
+        crate::log_files::write(&entry);
+
+        EMITTING.set(true);
+        crate::events::emit("log", entry);
+        EMITTING.set(false);
+    }
+}
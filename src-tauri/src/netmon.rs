@@ -0,0 +1,126 @@
+use crate::lifecycle::BridgeState;
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often to look for a wake from sleep.
+const WAKE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How far the wall clock may run ahead of the monotonic clock, which stops while the machine
+/// sleeps, before we assume it slept.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Pause between attempts to reach a peer after a network change.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Peers being reconnected, as `(id52, profile)`, so a burst of changes dials each peer once.
+static RECONNECTING: LazyLock<Mutex<HashSet<(String, String)>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Watches for network interface changes (netlink on Linux, route sockets elsewhere) and wakes
+/// from sleep, tells the endpoints to re-probe their paths and reconnects running bridges.
+/// Spawned once from `setup`.
+pub async fn monitor() {
+    use iroh::Watcher;
+
+    let monitor = match netwatch::netmon::Monitor::new().await {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            tracing::error!("not watching for network changes: {e:?}");
+            return;
+        }
+    };
+
+    let mut interfaces = monitor.interface_state();
+    let mut last = interfaces.get();
+    let mut wake_check = tokio::time::interval(WAKE_CHECK_INTERVAL);
+    let (mut wall_clock, mut monotonic) = (SystemTime::now(), Instant::now());
+
+    loop {
+        let reason = tokio::select! {
+            state = interfaces.updated() => {
+                let Ok(state) = state else {
+                    tracing::error!("network monitor stopped");
+                    return;
+                };
+                let major = state.is_major_change(&last);
+                last = state;
+                if !major {
+                    continue;
+                }
+                "network interfaces changed"
+            }
+            _ = wake_check.tick() => {
+                let slept = wall_clock
+                    .elapsed()
+                    .unwrap_or_default()
+                    .saturating_sub(monotonic.elapsed());
+                (wall_clock, monotonic) = (SystemTime::now(), Instant::now());
+                if slept < SLEEP_THRESHOLD {
+                    continue;
+                }
+                "woke from sleep"
+            }
+        };
+
+        tracing::info!(reason, interfaces = %last, "network changed, re-probing paths");
+        crate::endpoint::network_change().await;
+        reconnect_bridges();
+    }
+}
+
+/// Helper to mark every running bridge as reconnecting and dial each of their peers until it
+/// answers again.
+fn reconnect_bridges() {
+    let mut peers: HashSet<(String, String)> = HashSet::new();
+    for (url, id52, profile) in crate::running_tasks() {
+        crate::lifecycle::emit(&url, BridgeState::Reconnecting, None);
+        peers.insert((id52, profile));
+    }
+
+    for peer in peers {
+        if RECONNECTING
+            .lock()
+            .expect("Unable to unlock reconnecting peers")
+            .insert(peer.clone())
+        {
+            tokio::spawn(reconnect(peer));
+        }
+    }
+}
+
+async fn reconnect(peer: (String, String)) {
+    let (id52, profile) = &peer;
+
+    loop {
+        let reachability = crate::probe::dial(id52, profile, crate::probe::PREFLIGHT_TIMEOUT).await;
+
+        // bridges may have been stopped or started meanwhile
+        let urls: Vec<String> = crate::running_tasks()
+            .into_iter()
+            .filter(|(_, i, p)| i == id52 && p == profile)
+            .map(|(url, _, _)| url)
+            .collect();
+        if urls.is_empty() {
+            break;
+        }
+
+        if reachability.reachable {
+            tracing::info!(peer = id52, "peer reachable again");
+            for url in urls {
+                crate::lifecycle::emit(&url, BridgeState::Running, None);
+            }
+            break;
+        }
+
+        for url in urls {
+            crate::lifecycle::emit(&url, BridgeState::Reconnecting, reachability.error.clone());
+        }
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+
+    RECONNECTING
+        .lock()
+        .expect("Unable to unlock reconnecting peers")
+        .remove(&peer);
+}
@@ -0,0 +1,165 @@
+use crate::probe::PathKind;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// How often the latency of a watched peer is refreshed, path changes are reported immediately.
+const LATENCY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How the connection to a peer currently flows, sent to the frontend as `peer-path` events.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerPath {
+    pub id52: String,
+    pub path: PathKind,
+    pub remote_addr: Option<String>,
+    pub relay_url: Option<String>,
+    pub latency_ms: Option<f64>,
+}
+
+impl PeerPath {
+    fn new(
+        id52: &str,
+        conn_type: Option<iroh::endpoint::ConnectionType>,
+        latency: Option<Duration>,
+    ) -> Self {
+        use iroh::endpoint::ConnectionType;
+
+        let (remote_addr, relay_url) = match &conn_type {
+            Some(ConnectionType::Direct(addr)) => (Some(addr.to_string()), None),
+            Some(ConnectionType::Relay(url)) => (None, Some(url.to_string())),
+            Some(ConnectionType::Mixed(addr, url)) => {
+                (Some(addr.to_string()), Some(url.to_string()))
+            }
+            Some(ConnectionType::None) | None => (None, None),
+        };
+
+        PeerPath {
+            id52: id52.to_string(),
+            path: conn_type
+                .as_ref()
+                .map(PathKind::from)
+                .unwrap_or(PathKind::None),
+            remote_addr,
+            relay_url,
+            // rounded so the latency jitter does not flood the frontend with events
+            latency_ms: latency.map(|l| (l.as_secs_f64() * 10_000.0).round() / 10.0),
+        }
+    }
+}
+
+/// A peer with at least one running bridge.
+struct Watched {
+    bridges: usize,
+    task: tokio::task::JoinHandle<()>,
+}
+
+static WATCHED: LazyLock<Mutex<HashMap<String, Watched>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The last known path of every watched peer.
+static PATHS: LazyLock<Mutex<HashMap<String, PeerPath>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Starts tracking the path to `id52`, the primary or a fallback peer of a bridge of `profile`
+/// that just started.
+///
+/// A peer is watched from the endpoint of the first bridge that reaches it.
+pub fn watch(id52: &str, profile: &str) {
+    let mut watched = WATCHED.lock().expect("Unable to unlock watched peers");
+    match watched.get_mut(id52) {
+        Some(peer) => peer.bridges += 1,
+        None => {
+            let task = tokio::spawn(watch_peer(id52.to_string(), profile.to_string()));
+            watched.insert(id52.to_string(), Watched { bridges: 1, task });
+        }
+    }
+}
+
+/// Stops tracking the path to `id52` once its last bridge stopped.
+pub fn unwatch(id52: &str) {
+    let mut watched = WATCHED.lock().expect("Unable to unlock watched peers");
+    let Some(peer) = watched.get_mut(id52) else {
+        return;
+    };
+
+    peer.bridges -= 1;
+    if peer.bridges == 0
+        && let Some(peer) = watched.remove(id52)
+    {
+        peer.task.abort();
+        PATHS
+            .lock()
+            .expect("Unable to unlock peer paths")
+            .remove(id52);
+    }
+}
+
+/// The last known path of every peer with a running bridge.
+pub fn current() -> Vec<PeerPath> {
+    PATHS
+        .lock()
+        .expect("Unable to unlock peer paths")
+        .values()
+        .cloned()
+        .collect()
+}
+
+async fn watch_peer(id52: String, profile: String) {
+    use iroh::Watcher;
+
+    let id = match crate::peer_addr::endpoint_id(&id52) {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!(peer = id52, "not watching path of invalid peer id: {e:?}");
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(LATENCY_INTERVAL);
+    // `None` until the endpoint knows an address of the peer, i.e. before the first dial
+    let mut conn_type = None;
+
+    loop {
+        // fetched every round, the endpoint is replaced when the network settings or identity change
+        let endpoint = match crate::endpoint::get(&profile).await {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                tracing::error!(peer = id52, "stopped watching path: {e:?}");
+                return;
+            }
+        };
+        if conn_type.is_none() {
+            conn_type = endpoint.conn_type(id);
+        }
+
+        let path = PeerPath::new(
+            &id52,
+            conn_type.as_mut().map(|watcher| watcher.get()),
+            endpoint.latency(id),
+        );
+        let changed = PATHS
+            .lock()
+            .expect("Unable to unlock peer paths")
+            .insert(id52.clone(), path.clone())
+            .is_none_or(|previous| previous != path);
+        if changed {
+            tracing::debug!(?path, "peer path changed");
+            crate::events::emit("peer-path", path);
+        }
+
+        let disconnected = match conn_type.as_mut() {
+            Some(watcher) => tokio::select! {
+                updated = watcher.updated() => updated.is_err(),
+                _ = interval.tick() => false,
+            },
+            None => {
+                interval.tick().await;
+                false
+            }
+        };
+        if disconnected {
+            conn_type = None;
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::entry::AddrHints;
+use std::sync::LazyLock;
+
+/// Addresses we know for peers from their entries, consulted by the endpoint's discovery when
+/// `kulfi_utils::get_stream` dials a peer by its bare id52.
+static STATIC_PROVIDER: LazyLock<iroh::discovery::static_provider::StaticProvider> =
+    LazyLock::new(iroh::discovery::static_provider::StaticProvider::new);
+
+/// The discovery service serving the address hints, added to every endpoint `crate::endpoint`
+/// builds.
+pub fn static_provider() -> iroh::discovery::static_provider::StaticProvider {
+    STATIC_PROVIDER.clone()
+}
+
+pub fn endpoint_id(id52: &str) -> eyre::Result<iroh::EndpointId> {
+    let public_key = kulfi_utils::id52_to_public_key(id52)?;
+    Ok(iroh::EndpointId::from_bytes(&public_key.to_bytes())?)
+}
+
+/// Builds the address of `id52` from the hints of an entry, `None` if the hints carry no address.
+///
+/// A ticket must belong to the same peer as the URL, direct addresses and the relay URL are
+/// added on top of the ticket's addresses.
+pub fn endpoint_addr(id52: &str, hints: &AddrHints) -> eyre::Result<Option<iroh::EndpointAddr>> {
+    use std::str::FromStr;
+
+    let id = endpoint_id(id52)?;
+    let mut addr = match hints.ticket.as_deref().map(str::trim) {
+        Some(ticket) if !ticket.is_empty() => {
+            let ticket = iroh_tickets::endpoint::EndpointTicket::from_str(ticket)
+                .map_err(|e| eyre::anyhow!("invalid endpoint ticket: {e}"))?;
+            if ticket.endpoint_addr().id != id {
+                return Err(eyre::anyhow!(
+                    "the ticket is for a different peer than {id52}"
+                ));
+            }
+            ticket.endpoint_addr().clone()
+        }
+        _ => iroh::EndpointAddr::new(id),
+    };
+
+    for direct_addr in hints.direct_addrs.iter().map(|a| a.trim()) {
+        if direct_addr.is_empty() {
+            continue;
+        }
+        let direct_addr = std::net::SocketAddr::from_str(direct_addr)
+            .map_err(|e| eyre::anyhow!("invalid direct address {direct_addr:?}: {e}"))?;
+        addr = addr.with_ip_addr(direct_addr);
+    }
+
+    if let Some(relay_url) = hints.relay_url.as_deref().map(str::trim)
+        && !relay_url.is_empty()
+    {
+        let relay_url = iroh::RelayUrl::from_str(relay_url)
+            .map_err(|e| eyre::anyhow!("invalid relay URL {relay_url:?}: {e}"))?;
+        addr = addr.with_relay_url(relay_url);
+    }
+
+    Ok((!addr.is_empty()).then_some(addr))
+}
+
+/// Feeds the address hints of an entry to the endpoint, so dialing `id52` works without any
+/// discovery service. Replaces the hints previously given for the same peer.
+pub fn apply_hints(id52: &str, hints: &AddrHints) -> eyre::Result<()> {
+    let Some(addr) = endpoint_addr(id52, hints)? else {
+        return Ok(());
+    };
+
+    // the addresses themselves stay out of the logs, they end up in bug reports
+    tracing::info!(
+        peer = id52,
+        direct_addrs = addr.ip_addrs().count(),
+        relay_urls = addr.relay_urls().count(),
+        "using address hints"
+    );
+    STATIC_PROVIDER.set_endpoint_info(addr);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(seed: u8) -> (String, iroh::EndpointId) {
+        let id = iroh::SecretKey::from_bytes(&[seed; 32]).public();
+        (data_encoding::BASE32_DNSSEC.encode(id.as_bytes()), id)
+    }
+
+    fn ticket(addr: iroh::EndpointAddr) -> String {
+        iroh_tickets::endpoint::EndpointTicket::new(addr).to_string()
+    }
+
+    #[test]
+    fn no_hints_no_addr() {
+        let (id52, _) = peer(1);
+        assert!(
+            endpoint_addr(&id52, &AddrHints::default())
+                .unwrap()
+                .is_none()
+        );
+        apply_hints(&id52, &AddrHints::default()).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_ticket_for_another_peer() {
+        let (id52, _) = peer(1);
+        let (_, other) = peer(2);
+        let hints = AddrHints {
+            ticket: Some(ticket(
+                iroh::EndpointAddr::new(other).with_ip_addr("192.0.2.1:4433".parse().unwrap()),
+            )),
+            ..Default::default()
+        };
+        let error = endpoint_addr(&id52, &hints).unwrap_err();
+        assert!(error.to_string().contains("different peer"), "{error}");
+        assert!(apply_hints(&id52, &hints).is_err());
+    }
+
+    #[test]
+    fn rejects_an_invalid_ticket() {
+        let (id52, _) = peer(1);
+        let hints = AddrHints {
+            ticket: Some("endpointnotaticket".to_string()),
+            ..Default::default()
+        };
+        assert!(endpoint_addr(&id52, &hints).is_err());
+    }
+
+    #[test]
+    fn rejects_an_invalid_direct_address() {
+        let (id52, _) = peer(1);
+        let hints = AddrHints {
+            direct_addrs: vec!["192.0.2.1".to_string()],
+            ..Default::default()
+        };
+        let error = endpoint_addr(&id52, &hints).unwrap_err();
+        assert!(
+            error.to_string().contains("invalid direct address"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn rejects_an_invalid_relay_url() {
+        let (id52, _) = peer(1);
+        let hints = AddrHints {
+            relay_url: Some("not a url".to_string()),
+            ..Default::default()
+        };
+        let error = endpoint_addr(&id52, &hints).unwrap_err();
+        assert!(error.to_string().contains("invalid relay URL"), "{error}");
+    }
+
+    #[test]
+    fn merges_hints_with_the_ticket() {
+        let (id52, id) = peer(1);
+        let hints = AddrHints {
+            ticket: Some(ticket(
+                iroh::EndpointAddr::new(id).with_ip_addr("192.0.2.1:4433".parse().unwrap()),
+            )),
+            direct_addrs: vec![" 198.51.100.7:4433 ".to_string(), "".to_string()],
+            relay_url: Some("https://relay.example.com".to_string()),
+        };
+        let addr = endpoint_addr(&id52, &hints).unwrap().unwrap();
+
+        assert_eq!(addr.id, id);
+        let mut ip_addrs: Vec<String> = addr.ip_addrs().map(|a| a.to_string()).collect();
+        ip_addrs.sort();
+        assert_eq!(ip_addrs, ["192.0.2.1:4433", "198.51.100.7:4433"]);
+        let relay_urls: Vec<&iroh::RelayUrl> = addr.relay_urls().collect();
+        assert_eq!(
+            relay_urls,
+            [&"https://relay.example.com"
+                .parse::<iroh::RelayUrl>()
+                .unwrap()]
+        );
+    }
+}
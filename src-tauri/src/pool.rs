@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// How long the connection to a peer stays open after its last bridge stopped, so restarting a
+/// bridge or switching entries does not cost a new handshake.
+const IDLE_CLOSE: Duration = Duration::from_secs(60);
+
+/// How often a held connection is pinged, which also notices when it broke.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Longest wait before dialing a peer again after its held connection failed.
+const MAX_REDIAL: Duration = Duration::from_secs(60);
+
+/// The connections every bridge and probe opens its peer streams on, one per identity and peer,
+/// so several entries pointing at one peer share a connection whatever their kind.
+///
+/// kulfi_utils dials them on the first stream and closes them after a minute without streams.
+static PEER_CONNECTIONS: LazyLock<kulfi_utils::PeerStreamSenders> = LazyLock::new(Default::default);
+
+/// The connection held to a peer of at least one running bridge, or one waiting to be closed.
+struct Held {
+    bridges: usize,
+    connected: bool,
+    /// Dropping it makes the task holding the connection close it.
+    _close: tokio::sync::oneshot::Sender<()>,
+    idle_close: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// The held connections, by profile and peer id52, as connections to one peer from different
+/// identities are separate.
+static HELD: LazyLock<Mutex<HashMap<(String, String), Held>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// A held connection as listed in bug reports.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeldConnection {
+    pub profile: String,
+    pub id52: String,
+    /// Bridges using the connection, 0 while it waits to be closed.
+    pub bridges: usize,
+    pub connected: bool,
+}
+
+pub fn connections() -> kulfi_utils::PeerStreamSenders {
+    PEER_CONNECTIONS.clone()
+}
+
+/// Dials `id52` from `profile`'s identity, unless a connection is already held, and keeps the
+/// connection up for a bridge that just started, or for a probe.
+///
+/// The held connection keeps the path to the peer warm and shows early when the peer goes away,
+/// the streams of the bridges go over `connections`.
+pub fn acquire(profile: &str, id52: &str) {
+    let mut held = HELD.lock().expect("Unable to unlock held connections");
+    let peer = held
+        .entry((profile.to_string(), id52.to_string()))
+        .or_insert_with(|| {
+            let (close_tx, close_rx) = tokio::sync::oneshot::channel();
+            tokio::spawn(hold(profile.to_string(), id52.to_string(), close_rx));
+            Held {
+                bridges: 0,
+                connected: false,
+                _close: close_tx,
+                idle_close: None,
+            }
+        });
+    peer.bridges += 1;
+    if let Some(task) = peer.idle_close.take() {
+        task.abort();
+    }
+}
+
+/// Closes the connection held from `profile`'s identity to `id52` after `IDLE_CLOSE` unless it is
+/// acquired again meanwhile, called when a bridge stops.
+pub fn release(profile: &str, id52: &str) {
+    let mut held = HELD.lock().expect("Unable to unlock held connections");
+    let key = (profile.to_string(), id52.to_string());
+    let Some(peer) = held.get_mut(&key) else {
+        return;
+    };
+
+    peer.bridges = peer.bridges.saturating_sub(1);
+    if peer.bridges == 0 {
+        peer.idle_close = Some(tokio::spawn(close_when_idle(key)));
+    }
+}
+
+/// Closes the connection held from `profile`'s identity to `id52` right away unless another
+/// bridge uses it, called when an on-demand bridge goes idle.
+pub fn release_now(profile: &str, id52: &str) {
+    let mut held = HELD.lock().expect("Unable to unlock held connections");
+    let key = (profile.to_string(), id52.to_string());
+    let Some(peer) = held.get_mut(&key) else {
+        return;
+    };
+
+    peer.bridges = peer.bridges.saturating_sub(1);
+    if peer.bridges == 0 {
+        // dropping the entry tells `hold` to close the connection
+        tracing::info!(profile, peer = id52, "closing idle peer connection");
+        held.remove(&key);
+    }
+}
+
+/// Every held connection.
+pub fn held() -> Vec<HeldConnection> {
+    let mut held: Vec<HeldConnection> = HELD
+        .lock()
+        .expect("Unable to unlock held connections")
+        .iter()
+        .map(|((profile, id52), peer)| HeldConnection {
+            profile: profile.clone(),
+            id52: id52.clone(),
+            bridges: peer.bridges,
+            connected: peer.connected,
+        })
+        .collect();
+    held.sort_by(|a, b| (&a.profile, &a.id52).cmp(&(&b.profile, &b.id52)));
+    held
+}
+
+async fn close_when_idle(key: (String, String)) {
+    tokio::time::sleep(IDLE_CLOSE).await;
+
+    let mut held = HELD.lock().expect("Unable to unlock held connections");
+    if held.get(&key).is_some_and(|peer| peer.bridges == 0) {
+        // dropping the entry tells `hold` to close the connection
+        tracing::info!(
+            profile = key.0,
+            peer = key.1,
+            "closing idle peer connection"
+        );
+        held.remove(&key);
+    }
+}
+
+fn set_connected(profile: &str, id52: &str, connected: bool) {
+    if let Some(peer) = HELD
+        .lock()
+        .expect("Unable to unlock held connections")
+        .get_mut(&(profile.to_string(), id52.to_string()))
+    {
+        peer.connected = connected;
+    }
+}
+
+/// Dials `id52` and keeps the connection up until `close` fires or its sender is dropped,
+/// dialing again whenever the connection breaks.
+async fn hold(profile: String, id52: String, mut close: tokio::sync::oneshot::Receiver<()>) {
+    let mut failures = 0;
+    loop {
+        let dialed = tokio::select! {
+            _ = &mut close => return,
+            dialed = dial(&profile, &id52) => dialed,
+        };
+
+        match dialed {
+            Ok(conn) => {
+                tracing::info!(profile, peer = id52, "holding peer connection");
+                failures = 0;
+                set_connected(&profile, &id52, true);
+                let broken = tokio::select! {
+                    _ = &mut close => {
+                        conn.close(0u32.into(), b"idle");
+                        return;
+                    }
+                    broken = keep_alive(&conn) => broken,
+                };
+                set_connected(&profile, &id52, false);
+                tracing::warn!(
+                    profile,
+                    peer = id52,
+                    "held peer connection broke: {broken:?}"
+                );
+            }
+            Err(e) => {
+                failures += 1;
+                tracing::warn!(profile, peer = id52, "failed to dial peer: {e:?}");
+            }
+        }
+
+        tokio::select! {
+            _ = &mut close => return,
+            _ = tokio::time::sleep(redial(failures)) => {}
+        }
+    }
+}
+
+async fn dial(profile: &str, id52: &str) -> eyre::Result<iroh::endpoint::Connection> {
+    let endpoint = crate::endpoint::get(profile).await?;
+    let id = crate::peer_addr::endpoint_id(id52)?;
+    endpoint
+        .connect(id, kulfi_utils::APNS_IDENTITY)
+        .await
+        .map_err(|e| eyre::anyhow!("failed to connect: {e}"))
+}
+
+/// Pings `conn` every `KEEPALIVE` and returns why it broke, it does not return otherwise.
+async fn keep_alive(conn: &iroh::endpoint::Connection) -> eyre::Error {
+    loop {
+        tokio::select! {
+            reason = conn.closed() => return eyre::anyhow!("connection closed: {reason}"),
+            _ = tokio::time::sleep(KEEPALIVE) => {
+                match tokio::time::timeout(KEEPALIVE, kulfi_utils::ping(conn)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return e,
+                    Err(_) => return eyre::anyhow!("no pong within {}s", KEEPALIVE.as_secs()),
+                }
+            }
+        }
+    }
+}
+
+/// How long to wait before dialing again after `failures` failed dials in a row.
+fn redial(failures: u32) -> Duration {
+    Duration::from_secs(1)
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(MAX_REDIAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bridges(profile: &str, id52: &str) -> Option<usize> {
+        held()
+            .into_iter()
+            .find(|held| held.profile == profile && held.id52 == id52)
+            .map(|held| held.bridges)
+    }
+
+    #[test]
+    fn redial_backs_off() {
+        assert_eq!(redial(0), Duration::from_secs(1));
+        assert_eq!(redial(1), Duration::from_secs(2));
+        assert_eq!(redial(3), Duration::from_secs(8));
+        assert_eq!(redial(6), MAX_REDIAL);
+        assert_eq!(redial(u32::MAX), MAX_REDIAL);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shares_a_connection_per_profile_and_peer() {
+        acquire("test-pool-shared", "peer");
+        acquire("test-pool-shared", "peer");
+        acquire("test-pool-shared-other", "peer");
+        assert_eq!(bridges("test-pool-shared", "peer"), Some(2));
+        assert_eq!(bridges("test-pool-shared-other", "peer"), Some(1));
+
+        release("test-pool-shared", "peer");
+        assert_eq!(bridges("test-pool-shared", "peer"), Some(1));
+        release("test-pool-shared", "peer");
+        release("test-pool-shared-other", "peer");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn closes_once_idle() {
+        acquire("test-pool-idle", "peer");
+        release("test-pool-idle", "peer");
+        // kept for a while in case the bridge comes back
+        assert_eq!(bridges("test-pool-idle", "peer"), Some(0));
+
+        tokio::time::sleep(IDLE_CLOSE + Duration::from_secs(1)).await;
+        assert_eq!(bridges("test-pool-idle", "peer"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquiring_again_keeps_the_connection() {
+        acquire("test-pool-again", "peer");
+        release("test-pool-again", "peer");
+        tokio::time::sleep(IDLE_CLOSE / 2).await;
+        acquire("test-pool-again", "peer");
+
+        tokio::time::sleep(IDLE_CLOSE * 2).await;
+        assert_eq!(bridges("test-pool-again", "peer"), Some(1));
+        release("test-pool-again", "peer");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn release_now_closes_unless_shared() {
+        acquire("test-pool-now", "peer");
+        acquire("test-pool-now", "peer");
+        release_now("test-pool-now", "peer");
+        assert_eq!(bridges("test-pool-now", "peer"), Some(1));
+        release_now("test-pool-now", "peer");
+        assert_eq!(bridges("test-pool-now", "peer"), None);
+    }
+}
@@ -0,0 +1,309 @@
+use std::time::{Duration, Instant};
+
+/// How long the pre-flight check of a starting bridge waits for the peer.
+pub const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of pings `probe` sends once the connection is up.
+const PROBE_PINGS: usize = 5;
+
+/// Upper bound for the duration of a transfer test.
+const MAX_TRANSFER_SECONDS: u64 = 60;
+
+const TRANSFER_CHUNK: usize = 64 * 1024;
+
+/// How traffic to a peer flows, as reported by iroh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathKind {
+    Direct,
+    Relay,
+    Mixed,
+    None,
+}
+
+impl From<&iroh::endpoint::ConnectionType> for PathKind {
+    fn from(conn_type: &iroh::endpoint::ConnectionType) -> Self {
+        match conn_type {
+            iroh::endpoint::ConnectionType::Direct(_) => PathKind::Direct,
+            iroh::endpoint::ConnectionType::Relay(_) => PathKind::Relay,
+            iroh::endpoint::ConnectionType::Mixed(_, _) => PathKind::Mixed,
+            iroh::endpoint::ConnectionType::None => PathKind::None,
+        }
+    }
+}
+
+/// Result of dialing a peer.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reachability {
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub path: PathKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Reachability {
+    fn unreachable(error: String) -> Self {
+        Reachability {
+            reachable: false,
+            latency_ms: None,
+            path: PathKind::None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Opens a connection to `id52` from the endpoint of `profile` and pings it, giving up after
+/// `timeout`.
+///
+/// The latency is the round trip of the ping, measured after the handshake.
+pub async fn dial(id52: &str, profile: &str, timeout: Duration) -> Reachability {
+    match tokio::time::timeout(timeout, dial_(id52, profile)).await {
+        Ok(Ok(reachability)) => reachability,
+        Ok(Err(e)) => {
+            tracing::info!(peer = id52, error = ?e, "peer is unreachable");
+            Reachability::unreachable(e.to_string())
+        }
+        Err(_) => {
+            tracing::info!(peer = id52, "timed out dialing peer");
+            Reachability::unreachable(format!("no answer within {}s", timeout.as_secs()))
+        }
+    }
+}
+
+async fn dial_(id52: &str, profile: &str) -> eyre::Result<Reachability> {
+    let endpoint = crate::endpoint::get(profile).await?;
+    let id = crate::peer_addr::endpoint_id(id52)?;
+    let conn = endpoint
+        .connect(id, kulfi_utils::APNS_IDENTITY)
+        .await
+        .map_err(|e| eyre::anyhow!("failed to connect: {e}"))?;
+
+    let start = Instant::now();
+    let pinged = kulfi_utils::ping(&conn).await;
+    let latency = start.elapsed();
+    let path = path_kind(&endpoint, id);
+    conn.close(0u32.into(), b"done");
+    pinged?;
+
+    Ok(Reachability {
+        reachable: true,
+        latency_ms: Some(latency.as_millis() as u64),
+        path,
+        error: None,
+    })
+}
+
+fn path_kind(endpoint: &iroh::Endpoint, id: iroh::EndpointId) -> PathKind {
+    use iroh::Watcher;
+
+    endpoint
+        .conn_type(id)
+        .map(|mut conn_type| PathKind::from(&conn_type.get()))
+        .unwrap_or(PathKind::None)
+}
+
+/// Which service of the peer a transfer test talks to.
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferProtocol {
+    /// Download the URL path from the peer's HTTP service.
+    Http,
+    /// Send zeros to the peer's TCP service while reading whatever it sends back, best pointed at
+    /// a discard, echo or chargen style service.
+    Tcp,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferTest {
+    pub protocol: TransferProtocol,
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Throughput {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub elapsed_ms: u64,
+    pub upload_mbps: f64,
+    pub download_mbps: f64,
+}
+
+/// What `probe` measured, durations in milliseconds.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeReport {
+    /// The first ping, including connection setup if there was no connection yet.
+    pub connect_ms: f64,
+    pub rtt_min_ms: f64,
+    pub rtt_avg_ms: f64,
+    pub rtt_max_ms: f64,
+    pub path: PathKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throughput: Option<Throughput>,
+}
+
+/// Pings `id52` over streams of a pooled connection, the way bridges reach their peer, and
+/// optionally runs a timed transfer against one of the peer's services.
+///
+/// The pings go over the pooled stream connection of the bridges to `id52`, and the connection
+/// `crate::pool` holds to the peer stays up while probing.
+///
+/// `path` is the URL path requested by an HTTP transfer.
+pub async fn probe(
+    id52: &str,
+    profile: &str,
+    transfer: Option<TransferTest>,
+    path: &str,
+) -> eyre::Result<ProbeReport> {
+    crate::pool::acquire(profile, id52);
+    let report = probe_pooled(id52, profile, transfer, path).await;
+    crate::pool::release(profile, id52);
+    report
+}
+
+async fn probe_pooled(
+    id52: &str,
+    profile: &str,
+    transfer: Option<TransferTest>,
+    path: &str,
+) -> eyre::Result<ProbeReport> {
+    let endpoint = crate::endpoint::get(profile).await?;
+
+    let start = Instant::now();
+    ping_over_stream(&endpoint, id52, crate::pool::connections()).await?;
+    let connect = start.elapsed();
+
+    let mut rtts = Vec::with_capacity(PROBE_PINGS);
+    for _ in 0..PROBE_PINGS {
+        let start = Instant::now();
+        ping_over_stream(&endpoint, id52, crate::pool::connections()).await?;
+        rtts.push(start.elapsed());
+    }
+
+    let throughput = match transfer {
+        Some(test) => Some(run_transfer(&endpoint, id52, &test, path).await?),
+        None => None,
+    };
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    Ok(ProbeReport {
+        connect_ms: ms(connect),
+        rtt_min_ms: rtts.iter().copied().min().map(ms).unwrap_or_default(),
+        rtt_avg_ms: ms(rtts.iter().sum::<Duration>() / PROBE_PINGS as u32),
+        rtt_max_ms: rtts.iter().copied().max().map(ms).unwrap_or_default(),
+        path: path_kind(&endpoint, crate::peer_addr::endpoint_id(id52)?),
+        throughput,
+    })
+}
+
+/// Pings `id52` over a stream of `peer_connections`, connecting first if there is no connection.
+pub async fn ping_over_stream(
+    endpoint: &iroh::Endpoint,
+    id52: &str,
+    peer_connections: kulfi_utils::PeerStreamSenders,
+) -> eyre::Result<()> {
+    let (mut send, mut recv) = kulfi_utils::get_stream(
+        endpoint.clone(),
+        kulfi_utils::Protocol::Ping.into(),
+        id52.to_string(),
+        peer_connections,
+        crate::GRACEFUL.clone(),
+    )
+    .await?;
+
+    let pong = kulfi_utils::next_string(&mut recv).await?;
+    if pong.as_bytes() != kulfi_utils::PONG.trim_ascii_end() {
+        return Err(eyre::anyhow!("expected pong, got {pong:?}"));
+    }
+    send.finish()?;
+    Ok(())
+}
+
+async fn run_transfer(
+    endpoint: &iroh::Endpoint,
+    id52: &str,
+    test: &TransferTest,
+    path: &str,
+) -> eyre::Result<Throughput> {
+    let protocol = match test.protocol {
+        TransferProtocol::Http => kulfi_utils::Protocol::Http,
+        TransferProtocol::Tcp => kulfi_utils::Protocol::Tcp,
+    };
+    let (mut send, mut recv) = kulfi_utils::get_stream(
+        endpoint.clone(),
+        protocol.into(),
+        id52.to_string(),
+        crate::pool::connections(),
+        crate::GRACEFUL.clone(),
+    )
+    .await?;
+
+    let duration = Duration::from_secs(test.seconds.clamp(1, MAX_TRANSFER_SECONDS));
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::now() + duration;
+
+    let (bytes_sent, bytes_received) = match test.protocol {
+        TransferProtocol::Http => {
+            let (head, ()) = hyper::Request::get(format!("/{path}"))
+                .header(hyper::header::HOST, "127.0.0.1")
+                .body(())?
+                .into_parts();
+            send.write_all(&serde_json::to_vec(&kulfi_utils::http::Request::from(
+                head,
+            ))?)
+            .await?;
+            send.write_all(b"\n").await?;
+            send.finish()?;
+
+            let response: kulfi_utils::http::Response = kulfi_utils::next_json(&mut recv).await?;
+            if !(200..300).contains(&response.status) {
+                return Err(eyre::anyhow!(
+                    "the HTTP service answered with status {}",
+                    response.status
+                ));
+            }
+            (0, read_until(&mut recv, deadline).await?)
+        }
+        TransferProtocol::Tcp => {
+            let upload = async {
+                let buf = vec![0u8; TRANSFER_CHUNK];
+                let mut sent = 0u64;
+                while let Ok(written) = tokio::time::timeout_at(deadline, send.write(&buf)).await {
+                    sent += written? as u64;
+                }
+                send.finish()?;
+                Ok::<_, eyre::Report>(sent)
+            };
+            tokio::try_join!(upload, read_until(&mut recv, deadline))?
+        }
+    };
+
+    let elapsed = start.elapsed();
+    let mbps = |bytes: u64| bytes as f64 * 8.0 / elapsed.as_secs_f64() / 1_000_000.0;
+    Ok(Throughput {
+        bytes_sent,
+        bytes_received,
+        elapsed_ms: elapsed.as_millis() as u64,
+        upload_mbps: mbps(bytes_sent),
+        download_mbps: mbps(bytes_received),
+    })
+}
+
+/// Reads and discards `recv` until it ends or `deadline` passes, returning the bytes read.
+async fn read_until(
+    recv: &mut iroh::endpoint::RecvStream,
+    deadline: tokio::time::Instant,
+) -> eyre::Result<u64> {
+    let mut received = 0;
+    loop {
+        match tokio::time::timeout_at(deadline, recv.read_chunk(TRANSFER_CHUNK, true)).await {
+            Ok(Ok(Some(chunk))) => received += chunk.bytes.len() as u64,
+            Ok(Ok(None)) | Err(_) => return Ok(received),
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::{LazyLock, Mutex};
+
+/// The profile of installs from before profiles existed, its data stays at the top of the app
+/// data dir.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Directory in the app data dir holding one directory per additional profile.
+const PROFILES_DIR: &str = "profiles";
+
+/// Key of the active profile in the default profile's store.
+const ACTIVE_KEY: &str = "profile";
+
+static ACTIVE: LazyLock<Mutex<String>> = LazyLock::new(|| Mutex::new(DEFAULT_PROFILE.to_string()));
+
+/// What the frontend needs to know about a profile.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileInfo {
+    pub name: String,
+    pub id52: String,
+    /// Store file holding the profile's items and network settings.
+    pub store: String,
+    pub active: bool,
+    /// Why the secret key could not be loaded, `id52` is empty then.
+    pub identity_error: Option<String>,
+    /// Why the network settings could not be loaded, the profile's bridges fail to start then.
+    pub settings_error: Option<String>,
+}
+
+pub fn active() -> String {
+    ACTIVE
+        .lock()
+        .expect("Unable to unlock active profile")
+        .clone()
+}
+
+/// Whether `name` is a loaded profile, i.e. has an identity or failed to load one.
+pub fn exists(name: &str) -> bool {
+    crate::identity::id52(name).is_some() || crate::identity::load_error(name).is_some()
+}
+
+/// The store file, relative to the app data dir, holding the items and network settings of a
+/// profile. The default profile shares its store with the app wide settings.
+pub fn store_path(name: &str) -> String {
+    if name == DEFAULT_PROFILE {
+        "store.json".to_string()
+    } else {
+        format!("{PROFILES_DIR}/{name}/store.json")
+    }
+}
+
+/// The directory holding the secret key of a profile, created if missing.
+pub fn dir(app_handle: &tauri::AppHandle, name: &str) -> eyre::Result<std::path::PathBuf> {
+    use tauri::Manager;
+
+    let mut dir = app_handle.path().app_data_dir()?;
+    if name != DEFAULT_PROFILE {
+        dir = dir.join(PROFILES_DIR).join(name);
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Loads the identity and network settings of every profile and restores the active profile.
+/// Called from `setup` before any endpoint is built.
+pub fn load(app_handle: &tauri::AppHandle) {
+    use tauri_plugin_store::StoreExt;
+
+    for name in names(app_handle) {
+        // failures are reported with the profile, see `ProfileInfo::identity_error` and
+        // `ProfileInfo::settings_error`
+        let _ = crate::identity::load(app_handle, &name);
+        crate::endpoint::load_settings(app_handle, &name);
+    }
+
+    let active = app_handle
+        .store(store_path(DEFAULT_PROFILE))
+        .ok()
+        .and_then(|store| store.get(ACTIVE_KEY))
+        .and_then(|active| active.as_str().map(str::to_string));
+    match active {
+        Some(active) if exists(&active) => {
+            *ACTIVE.lock().expect("Unable to unlock active profile") = active
+        }
+        Some(active) => tracing::error!(active, "active profile does not exist, using default"),
+        None => {}
+    }
+}
+
+pub fn list() -> Vec<ProfileInfo> {
+    let active = active();
+    let mut profiles: Vec<ProfileInfo> = crate::identity::all()
+        .into_iter()
+        .map(|(name, id52)| ProfileInfo {
+            store: store_path(&name),
+            active: name == active,
+            id52,
+            identity_error: None,
+            settings_error: crate::endpoint::load_error(&name),
+            name,
+        })
+        .chain(
+            crate::identity::load_errors()
+                .into_iter()
+                .map(|(name, error)| ProfileInfo {
+                    store: store_path(&name),
+                    active: name == active,
+                    id52: String::new(),
+                    identity_error: Some(error),
+                    settings_error: crate::endpoint::load_error(&name),
+                    name,
+                }),
+        )
+        .collect();
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    profiles
+}
+
+/// Creates a profile with a fresh identity and the default network settings.
+pub fn create(app_handle: &tauri::AppHandle, name: &str) -> eyre::Result<ProfileInfo> {
+    validate_name(name)?;
+    if exists(name) {
+        return Err(eyre::anyhow!("profile {name} already exists"));
+    }
+
+    if let Err(e) = crate::identity::load(app_handle, name) {
+        crate::identity::forget(name);
+        return Err(e);
+    }
+    tracing::info!(profile = name, "created profile");
+    info(name)
+}
+
+/// Checks that a profile can be deleted: it exists and is neither the default nor the active
+/// one.
+pub fn deletable(name: &str) -> eyre::Result<()> {
+    if name == DEFAULT_PROFILE {
+        return Err(eyre::anyhow!("the default profile can not be deleted"));
+    }
+    if name == active() {
+        return Err(eyre::anyhow!(
+            "switch to another profile before deleting {name}"
+        ));
+    }
+    if !exists(name) {
+        return Err(eyre::anyhow!("profile {name} does not exist"));
+    }
+    Ok(())
+}
+
+/// Deletes a profile with its identity, items and network settings.
+///
+/// The caller stops the bridges dialing from the profile's identity first.
+pub async fn delete(app_handle: &tauri::AppHandle, name: &str) -> eyre::Result<()> {
+    use tauri_plugin_store::StoreExt;
+
+    deletable(name)?;
+    crate::endpoint::close(name).await;
+    crate::endpoint::forget(name);
+    crate::identity::forget(name);
+    // a profile created later under the same name must not see the old items
+    if let Some(store) = app_handle.get_store(store_path(name)) {
+        store.close_resource();
+    }
+    std::fs::remove_dir_all(dir(app_handle, name)?)?;
+
+    tracing::info!(profile = name, "deleted profile");
+    Ok(())
+}
+
+/// Makes `name` the active profile. The caller stops the bridges of the previous profile, their
+/// endpoints are closed here and rebuilt on first use.
+pub async fn switch(app_handle: &tauri::AppHandle, name: &str) -> eyre::Result<ProfileInfo> {
+    use tauri_plugin_store::StoreExt;
+
+    if !exists(name) {
+        return Err(eyre::anyhow!("profile {name} does not exist"));
+    }
+
+    // the caller stopped the bridges dialing from the previous profile
+    crate::endpoint::close(&active()).await;
+    *ACTIVE.lock().expect("Unable to unlock active profile") = name.to_string();
+
+    let store = app_handle.store(store_path(DEFAULT_PROFILE))?;
+    store.set(ACTIVE_KEY, name);
+    store.save()?;
+
+    tracing::info!(profile = name, "switched profile");
+    info(name)
+}
+
+fn info(name: &str) -> eyre::Result<ProfileInfo> {
+    list()
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .ok_or_else(|| eyre::anyhow!("profile {name} does not exist"))
+}
+
+/// Helper to list the default profile and every profile directory with a valid name.
+fn names(app_handle: &tauri::AppHandle) -> Vec<String> {
+    let mut names = vec![DEFAULT_PROFILE.to_string()];
+    let dir = match dir(app_handle, DEFAULT_PROFILE) {
+        Ok(dir) => dir.join(PROFILES_DIR),
+        Err(e) => {
+            tracing::error!("failed to find profiles: {e:?}");
+            return names;
+        }
+    };
+
+    match std::fs::read_dir(&dir) {
+        Ok(entries) => names.extend(
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| validate_name(name).is_ok()),
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::error!("failed to list {}: {e:?}", dir.display()),
+    }
+    names
+}
+
+/// Profile names end up in paths, so only allow a safe set of characters.
+fn validate_name(name: &str) -> eyre::Result<()> {
+    if name.is_empty()
+        || name.len() > 32
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(eyre::anyhow!(
+            "profile names must be 1 to 32 letters, digits, - or _"
+        ));
+    }
+    if name == DEFAULT_PROFILE {
+        return Err(eyre::anyhow!("{DEFAULT_PROFILE} is reserved"));
+    }
+    Ok(())
+}
@@ -0,0 +1,319 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Directory in the app data dir the bug reports are written to.
+const REPORT_DIR: &str = "reports";
+
+/// Fields of the summary and the stores naming the user's own servers or peer addresses, or
+/// holding request headers that may carry tokens, their values are replaced by `REDACTED`
+/// wherever they appear.
+const PRIVATE_FIELDS: &[&str] = &[
+    "relayUrls",
+    "pkarrUrls",
+    "dnsOrigin",
+    "relayUrl",
+    "directAddrs",
+    "remoteAddr",
+    "ticket",
+    "extraHeaders",
+    "hostOverride",
+    "rewriteOrigins",
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// What the app looked like when a bug report was made, `summary.json` in the archive.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Summary {
+    version: String,
+    os: &'static str,
+    arch: &'static str,
+    active_profile: String,
+    profiles: Vec<ProfileSummary>,
+    /// URLs of the running bridges.
+    bridges: Vec<String>,
+    peer_paths: Vec<crate::paths::PeerPath>,
+    active_peers: Vec<crate::failover::ActivePeer>,
+    stream_metrics: std::collections::HashMap<String, crate::retry::StreamMetrics>,
+    bridge_restarts: std::collections::HashMap<String, crate::supervisor::RestartStats>,
+    held_connections: Vec<crate::pool::HeldConnection>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileSummary {
+    name: String,
+    id52: String,
+    network: crate::endpoint::NetworkSettings,
+}
+
+/// Bundles the log files, the stores of every profile and a summary of the app's state into a
+/// zip archive in the app data dir and returns its path.
+///
+/// Peer ids in all of them are shortened to their first characters and `PRIVATE_FIELDS` are
+/// blanked out, secret keys are never included as they live outside the stores.
+pub fn export(app_handle: &tauri::AppHandle) -> eyre::Result<PathBuf> {
+    use tauri::Manager;
+    use tauri_plugin_store::StoreExt;
+    use zip::write::SimpleFileOptions;
+
+    let dir = app_handle.path().app_data_dir()?.join(REPORT_DIR);
+    std::fs::create_dir_all(&dir)?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let path = dir.join(format!("malai-client-report-{now}.zip"));
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    let summary = Summary {
+        version: app_handle.package_info().version.to_string(),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        active_profile: crate::profile::active(),
+        profiles: crate::profile::list()
+            .into_iter()
+            .map(|profile| ProfileSummary {
+                network: crate::endpoint::settings(&profile.name),
+                name: profile.name,
+                id52: profile.id52,
+            })
+            .collect(),
+        bridges: crate::running_tasks()
+            .into_iter()
+            .map(|(url, _, _)| url)
+            .collect(),
+        peer_paths: crate::paths::current(),
+        active_peers: crate::failover::current(),
+        stream_metrics: crate::retry::metrics(),
+        bridge_restarts: crate::supervisor::stats(),
+        held_connections: crate::pool::held(),
+    };
+    zip.start_file("summary.json", options)?;
+    zip.write_all(redact_json(serde_json::to_value(&summary)?)?.as_bytes())?;
+
+    for profile in crate::profile::list() {
+        let store = app_handle.store(&profile.store)?;
+        let entries: serde_json::Map<_, _> = store.entries().into_iter().collect();
+        zip.start_file(format!("config/{}", profile.store), options)?;
+        zip.write_all(redact_json(entries.into())?.as_bytes())?;
+    }
+
+    for file in crate::log_files::files() {
+        let Some(name) = file.file_name() else {
+            continue;
+        };
+        let log = std::fs::read_to_string(&file)?;
+        zip.start_file(format!("logs/{}", name.to_string_lossy()), options)?;
+        zip.write_all(redact_log(&log).as_bytes())?;
+    }
+
+    zip.finish()?;
+    tracing::info!(path = %path.display(), "exported bug report");
+    Ok(path)
+}
+
+/// Pretty prints `value` with `PRIVATE_FIELDS` blanked out and id52s shortened.
+fn redact_json(mut value: serde_json::Value) -> eyre::Result<String> {
+    redact_fields(&mut value);
+    Ok(redact(&serde_json::to_string_pretty(&value)?))
+}
+
+/// Replaces the values of `PRIVATE_FIELDS` anywhere in `value`, keeping how many items a list
+/// had and whether a field was set.
+fn redact_fields(value: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if !PRIVATE_FIELDS.contains(&name.as_str()) {
+                    redact_fields(value);
+                    continue;
+                }
+                match value {
+                    Value::Null => {}
+                    Value::Array(items) => {
+                        items.iter_mut().for_each(|item| *item = REDACTED.into())
+                    }
+                    value => *value = REDACTED.into(),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_fields),
+        _ => {}
+    }
+}
+
+/// Redacts a log file, which has no fields to go by: IP addresses other than loopback ones and
+/// URLs other than bridge URLs are replaced by `REDACTED`, id52s are shortened.
+fn redact_log(log: &str) -> String {
+    let mut redacted = String::with_capacity(log.len());
+    let mut rest = log;
+    while !rest.is_empty() {
+        let end = rest.find(is_separator).unwrap_or(rest.len());
+        let (word, after) = rest.split_at(end);
+        // a sentence may end right after an address
+        let address = word.trim_end_matches(['.', ':']);
+        if is_private_address(address) {
+            redacted.push_str(REDACTED);
+            redacted.push_str(&word[address.len()..]);
+        } else {
+            redacted.push_str(word);
+        }
+        let separator = after.chars().next().map_or(0, char::len_utf8);
+        redacted.push_str(&after[..separator]);
+        rest = &after[separator..];
+    }
+    redact(&redacted)
+}
+
+/// Characters around addresses in log lines, `Ip(192.0.2.1:4433)` and `addr=[::1]:4433` as
+/// logged by iroh and tracing included.
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || "\"'`()[]{}<>,;=".contains(c)
+}
+
+fn is_private_address(word: &str) -> bool {
+    use std::net::{IpAddr, SocketAddr};
+
+    if let Some((scheme, _)) = word.split_once("://") {
+        return !scheme.is_empty() && scheme != "kulfi";
+    }
+    if let Ok(addr) = word.parse::<SocketAddr>() {
+        return !addr.ip().is_loopback();
+    }
+    word.parse::<IpAddr>()
+        .is_ok_and(|ip| !ip.is_loopback() && !ip.is_unspecified())
+}
+
+/// Shortens every id52, a 52 characters long run of lowercase letters and digits, to its first
+/// 8 characters so the report still tells peers apart.
+fn redact(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_id52_char) {
+        let (before, from) = rest.split_at(start);
+        redacted.push_str(before);
+        let end = from.find(|c| !is_id52_char(c)).unwrap_or(from.len());
+        let (word, after) = from.split_at(end);
+        if word.len() == 52 {
+            redacted.push_str(&word[..8]);
+            redacted.push('…');
+        } else {
+            redacted.push_str(word);
+        }
+        rest = after;
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+fn is_id52_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID52: &str = "abcdefghijklmnopqrstuvwxyz0123456789abcdefghijklmnop";
+
+    #[test]
+    fn shortens_id52s() {
+        assert_eq!(
+            redact(&format!("peer {ID52} at kulfi://{ID52}/app.")),
+            "peer abcdefgh… at kulfi://abcdefgh…/app."
+        );
+    }
+
+    #[test]
+    fn keeps_other_words() {
+        let text = "Failed to dial 2 peers: timed out after 10s, ABCDEFGHIJ";
+        assert_eq!(redact(text), text);
+        // one character short and one too many
+        assert_eq!(redact(&ID52[1..]), &ID52[1..]);
+        assert_eq!(redact(&format!("{ID52}a")), format!("{ID52}a"));
+    }
+
+    #[test]
+    fn blanks_out_private_fields() {
+        let value = serde_json::json!({
+            "network": {
+                "relayUrls": ["https://relay.example.com/", "https://relay2.example.com/"],
+                "pkarrUrls": [],
+                "dnsOrigin": null,
+                "mdns": true,
+            },
+            "items": [{
+                "name": "app",
+                "urls": [{
+                    "url": format!("kulfi://{ID52}/app"),
+                    "relayUrl": "https://relay.example.com/",
+                    "directAddrs": ["192.168.1.2:1234"],
+                    "extraHeaders": ["Authorization: Bearer token"],
+                    "hostOverride": "app.example.com",
+                }],
+            }],
+            "peerPaths": [{ "id52": ID52, "path": "direct", "remoteAddr": "192.168.1.2:1234" }],
+        });
+
+        let redacted: serde_json::Value =
+            serde_json::from_str(&redact_json(value).unwrap()).unwrap();
+        assert_eq!(
+            redacted,
+            serde_json::json!({
+                "network": {
+                    "relayUrls": [REDACTED, REDACTED],
+                    "pkarrUrls": [],
+                    "dnsOrigin": null,
+                    "mdns": true,
+                },
+                "items": [{
+                    "name": "app",
+                    "urls": [{
+                        "url": "kulfi://abcdefgh…/app",
+                        "relayUrl": REDACTED,
+                        "directAddrs": [REDACTED],
+                        "extraHeaders": [REDACTED],
+                        "hostOverride": REDACTED,
+                    }],
+                }],
+                "peerPaths": [{ "id52": "abcdefgh…", "path": "direct", "remoteAddr": REDACTED }],
+            })
+        );
+    }
+
+    #[test]
+    fn redacts_addresses_in_logs() {
+        let log = format!(
+            "2025-01-01T00:00:00Z  INFO using address hints peer={ID52} addr=EndpointAddr {{ \
+             id: PublicKey({ID52}), addrs: {{Ip(192.0.2.1:4433), Ip([2001:db8::1]:4433), \
+             Relay(RelayUrl(\"https://relay.example.com./\"))}} }}\n\
+             2025-01-01T00:00:01Z  WARN failed to reach 198.51.100.7. retrying\n"
+        );
+        assert_eq!(
+            redact_log(&log),
+            "2025-01-01T00:00:00Z  INFO using address hints peer=abcdefgh… addr=EndpointAddr { \
+             id: PublicKey(abcdefgh…), addrs: {Ip([redacted]), Ip([[redacted]]:4433), \
+             Relay(RelayUrl(\"[redacted]\"))} }\n\
+             2025-01-01T00:00:01Z  WARN failed to reach [redacted]. retrying\n"
+        );
+    }
+
+    #[test]
+    fn keeps_local_addresses_and_bridge_urls_in_logs() {
+        let log = format!(
+            "TCP bridge listening on 127.0.0.1:8080 url=kulfi://{ID52}/app bound 0.0.0.0 v2.9 \
+             at 12:30:01 kulfi_client::pool::hold"
+        );
+        assert_eq!(
+            redact_log(&log),
+            "TCP bridge listening on 127.0.0.1:8080 url=kulfi://abcdefgh…/app bound 0.0.0.0 v2.9 \
+             at 12:30:01 kulfi_client::pool::hold"
+        );
+    }
+}
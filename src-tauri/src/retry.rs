@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// How a bridge retries opening a stream to its peer before giving up on a local connection.
+///
+/// The wait after a failed attempt starts at `initial_backoff_ms` and doubles up to
+/// `max_backoff_ms`, no attempt is started once `deadline_ms` has passed since the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub deadline_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 4_000,
+            deadline_ms: 15_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff_ms
+            .saturating_mul(1 << retry.min(16))
+            .min(self.max_backoff_ms);
+        Duration::from_millis(backoff)
+    }
+}
+
+/// Peer stream counters of a running bridge, see the `stream_metrics` command.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamMetrics {
+    pub opened: u64,
+    pub retries: u64,
+    pub failures: u64,
+}
+
+/// A retry or a final failure, sent to the frontend as `peer-stream` events.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum StreamEvent {
+    Retry {
+        url: String,
+        attempt: u32,
+        delay_ms: u64,
+        error: String,
+    },
+    Failed {
+        url: String,
+        attempts: u32,
+        error: String,
+    },
+}
+
+/// Counters of every running bridge, by URL.
+static METRICS: LazyLock<Mutex<HashMap<String, StreamMetrics>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Opens peer streams for the bridge started for `url`, following its retry policy.
+#[derive(Debug, Clone)]
+pub struct Dialer {
+    pub url: String,
+    pub policy: RetryPolicy,
+}
+
+impl Dialer {
+    pub fn new(url: &str, policy: Option<RetryPolicy>) -> Self {
+        Dialer {
+            url: url.to_string(),
+            policy: policy.unwrap_or_default(),
+        }
+    }
+
+    /// `kulfi_utils::get_stream` with retries, the endpoint of `profile` is fetched again for
+    /// every attempt as it may be rebuilt meanwhile.
+    pub async fn get_stream(
+        &self,
+        profile: &str,
+        protocol: kulfi_utils::Protocol,
+        remote_node_id52: &str,
+        peer_connections: kulfi_utils::PeerStreamSenders,
+        graceful: kulfi_utils::Graceful,
+    ) -> eyre::Result<(iroh::endpoint::SendStream, iroh::endpoint::RecvStream)> {
+        let deadline = Instant::now() + Duration::from_millis(self.policy.deadline_ms);
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let result = async {
+                kulfi_utils::get_stream(
+                    crate::endpoint::get(profile).await?,
+                    kulfi_utils::ProtocolHeader::from(protocol),
+                    remote_node_id52.to_string(),
+                    peer_connections.clone(),
+                    graceful.clone(),
+                )
+                .await
+            };
+
+            let error = match tokio::time::timeout_at(deadline.into(), result).await {
+                Ok(Ok(streams)) => {
+                    self.record(|m| m.opened += 1);
+                    return Ok(streams);
+                }
+                Ok(Err(e)) => e,
+                Err(_) => eyre::anyhow!("timed out opening a stream to {remote_node_id52}"),
+            };
+
+            let delay = self.policy.backoff(attempt - 1);
+            if attempt >= self.policy.attempts.max(1) || Instant::now() + delay >= deadline {
+                tracing::error!(
+                    url = self.url,
+                    attempt,
+                    "giving up on peer stream: {error:?}"
+                );
+                self.record(|m| m.failures += 1);
+                crate::events::emit(
+                    "peer-stream",
+                    StreamEvent::Failed {
+                        url: self.url.clone(),
+                        attempts: attempt,
+                        error: error.to_string(),
+                    },
+                );
+                return Err(error);
+            }
+
+            tracing::warn!(
+                url = self.url,
+                attempt,
+                ?delay,
+                "retrying peer stream: {error:?}"
+            );
+            self.record(|m| m.retries += 1);
+            crate::events::emit(
+                "peer-stream",
+                StreamEvent::Retry {
+                    url: self.url.clone(),
+                    attempt,
+                    delay_ms: delay.as_millis() as u64,
+                    error: error.to_string(),
+                },
+            );
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = graceful.cancelled() => return Err(error),
+            }
+        }
+    }
+
+    /// Helper to update the counters of the bridge, unless it was stopped meanwhile.
+    fn record(&self, update: impl FnOnce(&mut StreamMetrics)) {
+        if let Some(metrics) = METRICS
+            .lock()
+            .expect("Unable to unlock stream metrics")
+            .get_mut(&self.url)
+        {
+            update(metrics);
+        }
+    }
+}
+
+/// Starts counting from zero for the bridge of `url`, called when it starts.
+pub fn reset(url: &str) {
+    METRICS
+        .lock()
+        .expect("Unable to unlock stream metrics")
+        .insert(url.to_string(), StreamMetrics::default());
+}
+
+/// Drops the counters of the bridge of `url`, called when it stops.
+pub fn forget(url: &str) {
+    METRICS
+        .lock()
+        .expect("Unable to unlock stream metrics")
+        .remove(url);
+}
+
+pub fn metrics() -> HashMap<String, StreamMetrics> {
+    METRICS
+        .lock()
+        .expect("Unable to unlock stream metrics")
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1_000,
+            ..Default::default()
+        };
+        let backoffs: Vec<u128> = (0..6)
+            .map(|retry| policy.backoff(retry).as_millis())
+            .collect();
+        assert_eq!(backoffs, vec![100, 200, 400, 800, 1_000, 1_000]);
+    }
+
+    #[test]
+    fn backoff_does_not_overflow() {
+        let policy = RetryPolicy {
+            initial_backoff_ms: u64::MAX / 2,
+            max_backoff_ms: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(u64::MAX));
+    }
+
+    #[test]
+    fn missing_fields_take_the_defaults() {
+        let policy: RetryPolicy = serde_json::from_str(r#"{"attempts": 7}"#).unwrap();
+        assert_eq!(
+            policy,
+            RetryPolicy {
+                attempts: 7,
+                ..Default::default()
+            }
+        );
+    }
+}
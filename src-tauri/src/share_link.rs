@@ -0,0 +1,276 @@
+use crate::entry::{ConnectionType, SharedItem, UrlEntry};
+
+/// Fields of `UrlEntry` with their own query parameters, or none like `url`.
+const BASE_FIELDS: &[&str] = &[
+    "url",
+    "port",
+    "type",
+    "openInBrowser",
+    "ticket",
+    "directAddrs",
+    "relayUrl",
+];
+
+/// Fields of `UrlEntry` that only make sense on this device or are secret, never shared. HAR
+/// files are on this device and extra headers often carry tokens.
+const LOCAL_FIELDS: &[&str] = &["identity", "har", "extraHeaders"];
+
+/// Builds a self-describing share link for one entry of an item.
+///
+/// The link is the entry URL with the rest of the entry encoded in the query, e.g.
+/// `kulfi://<id52>/path?type=tcp&port=2222&name=My+Server`, so whoever imports it gets an item
+/// that is ready to run without further instructions. Address hints are carried as `ticket`,
+/// repeated `addr` and `relay` parameters. Every other setting is a parameter named like its
+/// field holding its JSON, strings quoted so they keep their type, except for `LOCAL_FIELDS`.
+pub fn to_share_link(name: &str, entry: &UrlEntry) -> eyre::Result<String> {
+    let mut link = parse_kulfi_url(&entry.url)?;
+    if link.query().is_some() {
+        return Err(eyre::anyhow!("URL must not contain a query: {}", entry.url));
+    }
+
+    {
+        let mut query = link.query_pairs_mut();
+        query.append_pair("type", entry.kind.as_str());
+        if let Some(port) = entry.port {
+            query.append_pair("port", &port.to_string());
+        }
+        if !name.is_empty() {
+            query.append_pair("name", name);
+        }
+        if entry.kind == ConnectionType::Http && !entry.open_in_browser {
+            query.append_pair("browser", "0");
+        }
+        if let Some(ticket) = &entry.hints.ticket {
+            query.append_pair("ticket", ticket);
+        }
+        for direct_addr in &entry.hints.direct_addrs {
+            query.append_pair("addr", direct_addr);
+        }
+        if let Some(relay_url) = &entry.hints.relay_url {
+            query.append_pair("relay", relay_url);
+        }
+
+        let serde_json::Value::Object(settings) = serde_json::to_value(entry)? else {
+            return Err(eyre::anyhow!("entry is not an object"));
+        };
+        for (key, value) in settings {
+            if BASE_FIELDS.contains(&key.as_str())
+                || LOCAL_FIELDS.contains(&key.as_str())
+                || value.is_null()
+            {
+                continue;
+            }
+            query.append_pair(&key, &value.to_string());
+        }
+    }
+
+    Ok(link.to_string())
+}
+
+/// Parses a link created by `to_share_link` back into the item name and the entry.
+///
+/// A plain `kulfi://` URL without a query is accepted too, it becomes an HTTP entry without a
+/// port. Unknown query parameters are ignored so links from newer clients still import.
+pub fn from_share_link(link: &str) -> eyre::Result<(String, UrlEntry)> {
+    let mut url = parse_kulfi_url(link.trim())?;
+
+    let mut name = None;
+    let mut entry = UrlEntry {
+        open_in_browser: true,
+        ..Default::default()
+    };
+    let mut settings = serde_json::Map::new();
+
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "type" => entry.kind = value.parse()?,
+            "port" => {
+                entry.port = Some(
+                    value
+                        .parse()
+                        .map_err(|e| eyre::anyhow!("invalid port {value:?}: {e}"))?,
+                )
+            }
+            "name" => name = Some(value.into_owned()),
+            "browser" => entry.open_in_browser = !matches!(value.as_ref(), "0" | "false"),
+            "ticket" => entry.hints.ticket = Some(value.into_owned()),
+            "addr" => entry.hints.direct_addrs.push(value.into_owned()),
+            "relay" => entry.hints.relay_url = Some(value.into_owned()),
+            key if BASE_FIELDS.contains(&key) || LOCAL_FIELDS.contains(&key) => {
+                tracing::debug!(%key, "ignoring share link parameter")
+            }
+            // unknown ones are dropped when the settings are deserialized, values that are not JSON
+            // are taken as strings
+            key => {
+                let value = serde_json::from_str::<serde_json::Value>(&value)
+                    .unwrap_or_else(|_| serde_json::Value::String(value.into_owned()));
+                settings.insert(key.to_string(), value);
+            }
+        }
+    }
+
+    if !settings.is_empty() {
+        let serde_json::Value::Object(mut full) = serde_json::to_value(&entry)? else {
+            return Err(eyre::anyhow!("entry is not an object"));
+        };
+        full.extend(settings);
+        entry = serde_json::from_value(serde_json::Value::Object(full))
+            .map_err(|e| eyre::anyhow!("invalid share link settings: {e}"))?;
+    }
+
+    url.set_query(None);
+    url.set_fragment(None);
+    entry.url = url.to_string();
+
+    let (id52, _) = crate::parse_url(&entry.url)?;
+    let name = name.unwrap_or_else(|| id52.to_string());
+
+    Ok((name, entry))
+}
+
+/// Turns every non-empty line of `text` into an entry, grouping entries with the same name into
+/// one item, in the order the names first appear.
+pub fn import_share_links(text: &str) -> eyre::Result<Vec<SharedItem>> {
+    let mut items: Vec<SharedItem> = Vec::new();
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let (name, entry) = from_share_link(line)?;
+        match items.iter_mut().find(|i| i.name == name) {
+            Some(item) => item.urls.push(entry),
+            None => items.push(SharedItem {
+                name,
+                urls: vec![entry],
+            }),
+        }
+    }
+
+    if items.is_empty() {
+        return Err(eyre::anyhow!("no share links found"));
+    }
+
+    Ok(items)
+}
+
+fn parse_kulfi_url(url: &str) -> eyre::Result<url::Url> {
+    let parsed = url::Url::parse(url).map_err(|e| eyre::anyhow!("invalid URL {url:?}: {e}"))?;
+    if parsed.scheme() != "kulfi" {
+        return Err(eyre::anyhow!("URL must start with kulfi://"));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(eyre::anyhow!("URL must contain the peer id52: {url}"));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::AddrHints;
+
+    const URL: &str = "kulfi://abcdefghijklmnopqrstuvwxyz0123456789abcdefghijklmnop/app";
+
+    fn entry() -> UrlEntry {
+        UrlEntry {
+            url: URL.to_string(),
+            port: Some(8080),
+            kind: ConnectionType::Http,
+            open_in_browser: false,
+            hints: AddrHints {
+                ticket: None,
+                direct_addrs: vec!["192.168.1.2:1234".to_string()],
+                relay_url: Some("https://relay.example.com/".to_string()),
+            },
+            preflight: Some(true),
+            identity: None,
+            retry: Some(crate::retry::RetryPolicy {
+                attempts: 5,
+                ..Default::default()
+            }),
+            fallbacks: vec![
+                "kulfi://bcdefghijklmnopqrstuvwxyz0123456789abcdefghijklmnopa/app".to_string(),
+            ],
+            failback_secs: Some(30),
+            strategy: Some(crate::failover::Strategy::RoundRobin),
+            health_check_secs: Some(10),
+            idle_secs: Some(300),
+            restart: Some(crate::supervisor::RestartPolicy {
+                mode: crate::supervisor::RestartMode::Always,
+                ..Default::default()
+            }),
+            access_log: Some(crate::access_log::Format::Combined),
+            inspect: Some(true),
+            har: None,
+            http_limits: Some(crate::http_limits::HttpLimits {
+                response_timeout_secs: Some(120),
+                ..Default::default()
+            }),
+            rewrite_origins: vec!["https://app.example.com".to_string()],
+            host_override: Some("app.example.com".to_string()),
+            extra_headers: vec![],
+            strip_headers: vec!["Cookie".to_string()],
+        }
+    }
+
+    #[test]
+    fn round_trips_an_entry() {
+        let link = to_share_link("My Server", &entry()).unwrap();
+        let (name, shared) = from_share_link(&link).unwrap();
+        assert_eq!(name, "My Server");
+        assert_eq!(shared, entry());
+    }
+
+    #[test]
+    fn leaves_out_local_fields() {
+        let entry = UrlEntry {
+            identity: Some("work".to_string()),
+            har: Some(crate::har::HarOptions {
+                mode: crate::har::Mode::Record,
+                file: Some("session.har".to_string()),
+                matching: Default::default(),
+            }),
+            extra_headers: vec!["Authorization: Bearer token".to_string()],
+            ..entry()
+        };
+        let link = to_share_link("My Server", &entry).unwrap();
+        assert!(!link.contains("work"));
+        assert!(!link.contains("har"));
+        assert!(!link.contains("token"));
+        let (_, shared) = from_share_link(&format!("{link}&identity=work")).unwrap();
+        assert_eq!(shared, self::entry());
+    }
+
+    #[test]
+    fn strings_that_look_like_json_stay_strings() {
+        for host in ["1234", "true", "null", "[1]", "\"quoted\""] {
+            let entry = UrlEntry {
+                host_override: Some(host.to_string()),
+                ..entry()
+            };
+            let link = to_share_link("My Server", &entry).unwrap();
+            let (_, shared) = from_share_link(&link).unwrap();
+            assert_eq!(shared, entry, "{host}");
+        }
+    }
+
+    #[test]
+    fn unquoted_strings_are_accepted() {
+        let (_, shared) = from_share_link(&format!("{URL}?hostOverride=app.example.com")).unwrap();
+        assert_eq!(shared.host_override.as_deref(), Some("app.example.com"));
+    }
+
+    #[test]
+    fn plain_url_is_an_http_entry() {
+        let (name, shared) = from_share_link(URL).unwrap();
+        assert_eq!(name, "abcdefghijklmnopqrstuvwxyz0123456789abcdefghijklmnop");
+        assert_eq!(shared.kind, ConnectionType::Http);
+        assert!(shared.open_in_browser);
+        assert_eq!(shared.port, None);
+    }
+
+    #[test]
+    fn ignores_unknown_parameters() {
+        let (_, shared) = from_share_link(&format!("{URL}?type=tcp&port=22&future=1")).unwrap();
+        assert_eq!(shared.kind, ConnectionType::Tcp);
+        assert_eq!(shared.port, Some(22));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::lifecycle::BridgeState;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::oneshot;
+
+/// A bridge that ran this long before failing is considered healthy again, its consecutive
+/// restarts start over.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// When a bridge that stopped on its own, e.g. on an accept error, is started again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    Never,
+    /// Restart up to `max_restarts` times in a row.
+    #[default]
+    OnFailure,
+    /// Restart for as long as the bridge is not stopped by the user.
+    Always,
+}
+
+/// The restart policy of a bridge, the wait before a restart starts at `initial_backoff_ms` and
+/// doubles with every consecutive restart up to `max_backoff_ms`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RestartPolicy {
+    pub mode: RestartMode,
+    pub max_restarts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            mode: RestartMode::OnFailure,
+            max_restarts: 5,
+            initial_backoff_ms: 1_000,
+            max_backoff_ms: 60_000,
+        }
+    }
+}
+
+impl RestartPolicy {
+    fn allows(&self, restart: u32) -> bool {
+        match self.mode {
+            RestartMode::Never => false,
+            RestartMode::OnFailure => restart <= self.max_restarts,
+            RestartMode::Always => true,
+        }
+    }
+
+    fn backoff(&self, restart: u32) -> Duration {
+        let backoff = self
+            .initial_backoff_ms
+            .saturating_mul(1 << restart.saturating_sub(1).min(16))
+            .min(self.max_backoff_ms);
+        Duration::from_millis(backoff)
+    }
+}
+
+/// How often a bridge was restarted since the user started it, see the `bridge_restarts`
+/// command.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartStats {
+    pub restarts: u32,
+    pub last_reason: Option<String>,
+    /// Unix time of the last restart, in seconds.
+    pub last_restart_at: Option<u64>,
+}
+
+static STATS: LazyLock<Mutex<HashMap<String, RestartStats>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Starts counting from zero for the bridge of `url`, called when the user starts it.
+pub fn reset(url: &str) {
+    STATS
+        .lock()
+        .expect("Unable to unlock restart stats")
+        .insert(url.to_string(), RestartStats::default());
+}
+
+pub fn stats() -> HashMap<String, RestartStats> {
+    STATS
+        .lock()
+        .expect("Unable to unlock restart stats")
+        .clone()
+}
+
+fn record(url: &str, reason: &str) {
+    let mut stats = STATS.lock().expect("Unable to unlock restart stats");
+    let stats = stats.entry(url.to_string()).or_default();
+    stats.restarts += 1;
+    stats.last_reason = Some(reason.to_string());
+    stats.last_restart_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs());
+}
+
+/// Runs the bridge of `url` and starts it again per `policy` when it stops on its own, until
+/// `shutdown_rx` fires.
+///
+/// `run` starts the bridge once with its own shutdown and startup channels and returns an error
+/// if it stopped for any other reason than its shutdown. Only the first start is reported on
+/// `startup_tx`, a bridge that fails to start then is not restarted.
+pub async fn supervise<F, Fut>(
+    url: String,
+    policy: RestartPolicy,
+    mut shutdown_rx: oneshot::Receiver<()>,
+    startup_tx: oneshot::Sender<Result<(), String>>,
+    mut run: F,
+) where
+    F: FnMut(oneshot::Receiver<()>, oneshot::Sender<Result<(), String>>) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let mut startup_tx = Some(startup_tx);
+    let mut restart = 0;
+
+    loop {
+        let (bridge_shutdown_tx, bridge_shutdown_rx) = oneshot::channel();
+        let (bridge_startup_tx, mut bridge_startup_rx) = oneshot::channel();
+        let started = Instant::now();
+        let bridge = run(bridge_shutdown_rx, bridge_startup_tx);
+        tokio::pin!(bridge);
+
+        // the bridge reports its start before it stops, so both are awaited from one select
+        let mut starting = true;
+        let error = loop {
+            tokio::select! {
+                biased;
+                _ = &mut shutdown_rx => {
+                    let _ = bridge_shutdown_tx.send(());
+                    let _ = bridge.await;
+                    return;
+                }
+                result = &mut bridge_startup_rx, if starting => {
+                    starting = false;
+                    let result = result
+                        .unwrap_or_else(|_| Err("bridge exited before it started".to_string()));
+                    if let Some(startup_tx) = startup_tx.take() {
+                        let failed = result.is_err();
+                        let _ = startup_tx.send(result);
+                        if failed {
+                            return;
+                        }
+                    } else if let Err(e) = result {
+                        break e;
+                    } else {
+                        tracing::info!(url, "bridge restarted");
+                        crate::lifecycle::emit(&url, BridgeState::Running, None);
+                    }
+                }
+                result = &mut bridge => {
+                    break result.err().unwrap_or_else(|| "bridge stopped".to_string());
+                }
+            }
+        };
+
+        if started.elapsed() >= STABLE_AFTER {
+            restart = 0;
+        }
+        restart += 1;
+
+        if !policy.allows(restart) {
+            tracing::error!(url, "bridge failed, not restarting: {error}");
+            crate::stop_task(&url, Some(error));
+            return;
+        }
+
+        let delay = policy.backoff(restart);
+        tracing::warn!(url, restart, ?delay, "bridge failed, restarting: {error}");
+        record(&url, &error);
+        crate::lifecycle::emit(&url, BridgeState::Restarting, Some(error));
+
+        tokio::select! {
+            _ = &mut shutdown_rx => return,
+            _ = tokio::time::sleep(delay) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mode: RestartMode) -> RestartPolicy {
+        RestartPolicy {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn never_restarts() {
+        assert!(!policy(RestartMode::Never).allows(1));
+    }
+
+    #[test]
+    fn restarts_on_failure_up_to_max_restarts() {
+        let policy = policy(RestartMode::OnFailure);
+        assert!(policy.allows(1));
+        assert!(policy.allows(5));
+        assert!(!policy.allows(6));
+
+        let policy = RestartPolicy {
+            max_restarts: 0,
+            ..policy
+        };
+        assert!(!policy.allows(1));
+    }
+
+    #[test]
+    fn always_restarts() {
+        assert!(policy(RestartMode::Always).allows(u32::MAX));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RestartPolicy::default();
+        let backoffs: Vec<u64> = (1..=8)
+            .map(|restart| policy.backoff(restart).as_millis() as u64)
+            .collect();
+        assert_eq!(
+            backoffs,
+            [1_000, 2_000, 4_000, 8_000, 16_000, 32_000, 60_000, 60_000]
+        );
+    }
+
+    #[test]
+    fn backoff_does_not_overflow() {
+        let policy = RestartPolicy {
+            mode: RestartMode::Always,
+            initial_backoff_ms: u64::MAX / 2,
+            max_backoff_ms: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(u64::MAX / 2));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(u64::MAX));
+
+        let policy = RestartPolicy::default();
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(60));
+    }
+}
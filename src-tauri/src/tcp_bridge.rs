@@ -1,12 +1,22 @@
+use tracing::Instrument;
+
+/// Listens on the port in `bound_port`, a random one if it is 0, and stores the port it got
+/// there, so a restart binds the same port.
+#[allow(clippy::too_many_arguments)]
 pub async fn tcp_bridge(
-    port: u16,
-    proxy_target: String,
+    bound_port: std::sync::Arc<std::sync::atomic::AtomicU16>,
+    peers: crate::failover::Peers,
+    profile: String,
+    dialer: crate::retry::Dialer,
+    activity: crate::idle::Activity,
     graceful: kulfi_utils::Graceful,
     mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
     startup_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-) {
+) -> Result<(), String> {
     use eyre::WrapErr;
+    use std::sync::atomic::Ordering;
 
+    let port = bound_port.load(Ordering::SeqCst);
     let listener = match tokio::net::TcpListener::bind(format!("127.0.0.1:{port}"))
         .await
         .wrap_err_with(|| {
@@ -15,16 +25,19 @@ pub async fn tcp_bridge(
         Ok(listener) => listener,
         Err(e) => {
             let error_msg = format!("Failed to bind TCP to port {port}: {e}");
-            eprintln!("{error_msg}");
-            let _ = startup_tx.send(Err(error_msg));
-            return;
+            tracing::error!("{error_msg}");
+            let _ = startup_tx.send(Err(error_msg.clone()));
+            return Err(error_msg);
         }
     };
 
-    println!("TCP bridge listening on 127.0.0.1:{port}");
+    let port = listener.local_addr().map_or(port, |addr| addr.port());
+    bound_port.store(port, Ordering::SeqCst);
+    tracing::info!("TCP bridge listening on 127.0.0.1:{port}");
     let _ = startup_tx.send(Ok(()));
 
-    let peer_connections = kulfi_utils::PeerStreamSenders::default();
+    let peer_connections = crate::pool::connections();
+    let mut acceptor = crate::accept::Acceptor::new(activity.url());
 
     loop {
         tokio::select! {
@@ -32,35 +45,54 @@ pub async fn tcp_bridge(
                 tracing::info!("Stopping TCP bridge.");
                 break;
             }
-            val = listener.accept() => {
+            val = acceptor.accept(&listener) => {
                 match val {
                     Ok((stream, _addr)) => {
                         tracing::info!("got TCP connection");
-                        let self_endpoint = kulfi_utils::global_iroh_endpoint().await;
                         let graceful_for_conn = graceful.clone();
                         let peer_connections = peer_connections.clone();
-                        let proxy_target = proxy_target.clone();
+                        let peers = peers.clone();
+                        let profile = profile.clone();
+                        let dialer = dialer.clone();
+                        let open = activity.open();
                         graceful.spawn(async move {
-                            println!("forwarding tcp connection to {proxy_target}");
-                            if let Err(e) = kulfi_utils::tcp_to_peer(
-                                kulfi_utils::Protocol::Tcp.into(),
-                                self_endpoint,
-                                stream,
-                                &proxy_target,
-                                peer_connections,
-                                graceful_for_conn,
-                            )
-                            .await
-                            {
+                            let _open = open;
+                            let result = async {
+                                let ((send, recv), _lease) = peers
+                                    .dial(|id52| {
+                                        let (dialer, profile) = (&dialer, &profile);
+                                        let peer_connections = peer_connections.clone();
+                                        let graceful = graceful_for_conn.clone();
+                                        async move {
+                                            tracing::info!("forwarding tcp connection to {id52}");
+                                            dialer
+                                                .get_stream(
+                                                    profile,
+                                                    kulfi_utils::Protocol::Tcp,
+                                                    &id52,
+                                                    peer_connections,
+                                                    graceful,
+                                                )
+                                                .await
+                                        }
+                                    })
+                                    .await?;
+                                kulfi_utils::pipe_tcp_stream_over_iroh(stream, send, recv).await
+                            };
+                            if let Err(e) = result.await {
                                 tracing::error!("failed to proxy tcp: {e:?}");
                             }
-                        });
+                        }
+                        .in_current_span());
                     }
                     Err(e) => {
                         tracing::error!("failed to accept TCP: {e:?}");
+                        return Err(format!("Failed to accept TCP: {e}"));
                     }
                 }
             }
         }
     }
+
+    Ok(())
 }
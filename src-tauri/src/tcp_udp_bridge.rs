@@ -1,16 +1,25 @@
+/// Listens on the port in `bound_port`, a random one if it is 0, and stores the port it got
+/// there, so a restart binds the same port.
+#[allow(clippy::too_many_arguments)]
 pub async fn tcp_udp_bridge(
-    port: u16,
+    bound_port: std::sync::Arc<std::sync::atomic::AtomicU16>,
     proxy_target: String,
+    profile: String,
+    dialer: crate::retry::Dialer,
+    activity: crate::idle::Activity,
     graceful: kulfi_utils::Graceful,
     mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
     startup_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-) {
+) -> Result<(), String> {
     use eyre::WrapErr;
     use std::collections::HashMap;
     use std::net::SocketAddr;
     use std::sync::Arc;
+    use std::sync::atomic::Ordering;
     use tokio::sync::Mutex;
+    use tracing::Instrument;
 
+    let port = bound_port.load(Ordering::SeqCst);
     // Bind TCP and UDP on the same port (different protocols, so no conflict)
     let tcp_listener = match tokio::net::TcpListener::bind(format!("127.0.0.1:{port}"))
         .await
@@ -19,12 +28,14 @@ pub async fn tcp_udp_bridge(
         Ok(l) => l,
         Err(e) => {
             let error_msg = format!("Failed to bind TCP to port {port}: {e}");
-            eprintln!("{error_msg}");
-            let _ = startup_tx.send(Err(error_msg));
-            return;
+            tracing::error!("{error_msg}");
+            let _ = startup_tx.send(Err(error_msg.clone()));
+            return Err(error_msg);
         }
     };
 
+    // a random TCP port is then also taken for UDP
+    let port = tcp_listener.local_addr().map_or(port, |addr| addr.port());
     let udp_socket = match tokio::net::UdpSocket::bind(format!("127.0.0.1:{port}"))
         .await
         .wrap_err_with(|| format!("Can not listen UDP on port {port}"))
@@ -32,17 +43,19 @@ pub async fn tcp_udp_bridge(
         Ok(s) => Arc::new(s),
         Err(e) => {
             let error_msg = format!("Failed to bind UDP to port {port}: {e}");
-            eprintln!("{error_msg}");
-            let _ = startup_tx.send(Err(error_msg));
-            return;
+            tracing::error!("{error_msg}");
+            let _ = startup_tx.send(Err(error_msg.clone()));
+            return Err(error_msg);
         }
     };
 
-    println!("TCP+UDP bridge listening on 127.0.0.1:{port}");
+    bound_port.store(port, Ordering::SeqCst);
+    tracing::info!("TCP+UDP bridge listening on 127.0.0.1:{port}");
     let _ = startup_tx.send(Ok(()));
 
-    let tcp_peer_connections = kulfi_utils::PeerStreamSenders::default();
-    let udp_peer_connections = kulfi_utils::PeerStreamSenders::default();
+    // TCP and UDP streams share one connection to the peer
+    let peer_connections = crate::pool::connections();
+    let mut acceptor = crate::accept::Acceptor::new(activity.url());
     let udp_sessions: Arc<Mutex<HashMap<SocketAddr, tokio::sync::mpsc::Sender<Vec<u8>>>>> =
         Arc::new(Mutex::new(HashMap::new()));
 
@@ -55,31 +68,39 @@ pub async fn tcp_udp_bridge(
                 break;
             }
             // TCP accept
-            val = tcp_listener.accept() => {
+            val = acceptor.accept(&tcp_listener) => {
                 match val {
                     Ok((stream, _addr)) => {
                         tracing::info!("got TCP connection");
-                        let self_endpoint = kulfi_utils::global_iroh_endpoint().await;
                         let graceful_for_conn = graceful.clone();
-                        let peer_connections = tcp_peer_connections.clone();
+                        let peer_connections = peer_connections.clone();
                         let proxy_target = proxy_target.clone();
+                        let profile = profile.clone();
+                        let dialer = dialer.clone();
+                        let open = activity.open();
                         graceful.spawn(async move {
-                            if let Err(e) = kulfi_utils::tcp_to_peer(
-                                kulfi_utils::Protocol::Tcp.into(),
-                                self_endpoint,
-                                stream,
-                                &proxy_target,
-                                peer_connections,
-                                graceful_for_conn,
-                            )
-                            .await
-                            {
+                            let _open = open;
+                            let result = async {
+                                let (send, recv) = dialer
+                                    .get_stream(
+                                        &profile,
+                                        kulfi_utils::Protocol::Tcp,
+                                        &proxy_target,
+                                        peer_connections,
+                                        graceful_for_conn,
+                                    )
+                                    .await?;
+                                kulfi_utils::pipe_tcp_stream_over_iroh(stream, send, recv).await
+                            };
+                            if let Err(e) = result.await {
                                 tracing::error!("failed to proxy tcp: {e:?}");
                             }
-                        });
+                        }
+                        .in_current_span());
                     }
                     Err(e) => {
                         tracing::error!("failed to accept TCP: {e:?}");
+                        return Err(format!("Failed to accept TCP: {e}"));
                     }
                 }
             }
@@ -87,6 +108,7 @@ pub async fn tcp_udp_bridge(
             result = udp_socket.recv_from(&mut udp_buf) => {
                 match result {
                     Ok((n, client_addr)) => {
+                        activity.touch();
                         let data = udp_buf[..n].to_vec();
                         let mut sessions_guard = udp_sessions.lock().await;
 
@@ -99,7 +121,9 @@ pub async fn tcp_udp_bridge(
                                     client_addr,
                                     data,
                                     proxy_target.clone(),
-                                    udp_peer_connections.clone(),
+                                    profile.clone(),
+                                    dialer.clone(),
+                                    peer_connections.clone(),
                                     udp_sessions.clone(),
                                     graceful.clone(),
                                 ).await;
@@ -111,33 +135,42 @@ pub async fn tcp_udp_bridge(
                                 client_addr,
                                 data,
                                 proxy_target.clone(),
-                                udp_peer_connections.clone(),
+                                profile.clone(),
+                                dialer.clone(),
+                                peer_connections.clone(),
                                 udp_sessions.clone(),
                                 graceful.clone(),
                             ).await;
                         }
                     }
+                    // an ICMP error for an earlier datagram, the socket itself is fine
+                    Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                        tracing::warn!("failed to recv UDP: {e:?}");
+                    }
                     Err(e) => {
                         tracing::error!("failed to recv UDP: {e:?}");
+                        return Err(format!("Failed to recv UDP: {e}"));
                     }
                 }
             }
         }
     }
+
+    Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn start_udp_session(
     socket: std::sync::Arc<tokio::net::UdpSocket>,
     client_addr: std::net::SocketAddr,
     initial_data: Vec<u8>,
     remote_node_id52: String,
+    profile: String,
+    dialer: crate::retry::Dialer,
     peer_connections: kulfi_utils::PeerStreamSenders,
     sessions: std::sync::Arc<
         tokio::sync::Mutex<
-            std::collections::HashMap<
-                std::net::SocketAddr,
-                tokio::sync::mpsc::Sender<Vec<u8>>,
-            >,
+            std::collections::HashMap<std::net::SocketAddr, tokio::sync::mpsc::Sender<Vec<u8>>>,
         >,
     >,
     graceful: kulfi_utils::Graceful,
@@ -150,56 +183,57 @@ async fn start_udp_session(
     }
 
     let graceful_for_session = graceful.clone();
-    graceful.spawn(async move {
-        let self_endpoint = kulfi_utils::global_iroh_endpoint().await;
-        let header = kulfi_utils::ProtocolHeader::from(kulfi_utils::Protocol::Udp);
-
-        let result = async {
-            let (mut send, mut recv) = kulfi_utils::get_stream(
-                self_endpoint,
-                header,
-                remote_node_id52.to_string(),
-                peer_connections,
-                graceful_for_session.clone(),
-            )
-            .await?;
-
-            kulfi_utils::write_framed_datagram(&mut send, &initial_data).await?;
-
-            let socket_for_recv = socket.clone();
-            let recv_task = tokio::spawn(async move {
-                loop {
-                    match kulfi_utils::read_framed_datagram(&mut recv).await {
-                        Ok(data) => {
-                            if let Err(e) = socket_for_recv.send_to(&data, client_addr).await {
-                                tracing::error!("failed to send UDP response: {e:?}");
+    graceful.spawn(
+        async move {
+            let result = async {
+                let (mut send, mut recv) = dialer
+                    .get_stream(
+                        &profile,
+                        kulfi_utils::Protocol::Udp,
+                        &remote_node_id52,
+                        peer_connections,
+                        graceful_for_session.clone(),
+                    )
+                    .await?;
+
+                kulfi_utils::write_framed_datagram(&mut send, &initial_data).await?;
+
+                let socket_for_recv = socket.clone();
+                let recv_task = tokio::spawn(async move {
+                    loop {
+                        match kulfi_utils::read_framed_datagram(&mut recv).await {
+                            Ok(data) => {
+                                if let Err(e) = socket_for_recv.send_to(&data, client_addr).await {
+                                    tracing::error!("failed to send UDP response: {e:?}");
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                tracing::trace!("iroh recv stream ended: {e:?}");
                                 break;
                             }
                         }
-                        Err(e) => {
-                            tracing::trace!("iroh recv stream ended: {e:?}");
-                            break;
-                        }
                     }
+                });
+
+                while let Some(data) = rx.recv().await {
+                    kulfi_utils::write_framed_datagram(&mut send, &data).await?;
                 }
-            });
 
-            while let Some(data) = rx.recv().await {
-                kulfi_utils::write_framed_datagram(&mut send, &data).await?;
-            }
+                send.finish()?;
+                let _ = recv_task.await;
 
-            send.finish()?;
-            let _ = recv_task.await;
+                Ok::<(), eyre::Report>(())
+            }
+            .await;
 
-            Ok::<(), eyre::Report>(())
-        }
-        .await;
+            if let Err(e) = result {
+                tracing::error!("UDP session error: {e:?}");
+            }
 
-        if let Err(e) = result {
-            tracing::error!("UDP session error: {e:?}");
+            let mut sessions_guard = sessions.lock().await;
+            sessions_guard.remove(&client_addr);
         }
-
-        let mut sessions_guard = sessions.lock().await;
-        sessions_guard.remove(&client_addr);
-    });
+        .in_current_span(),
+    );
 }
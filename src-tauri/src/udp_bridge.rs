@@ -2,16 +2,25 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tracing::Instrument;
 
+/// Listens on the port in `bound_port`, a random one if it is 0, and stores the port it got
+/// there, so a restart binds the same port.
+#[allow(clippy::too_many_arguments)]
 pub async fn udp_bridge(
-    port: u16,
+    bound_port: Arc<std::sync::atomic::AtomicU16>,
     proxy_target: String,
+    profile: String,
+    dialer: crate::retry::Dialer,
+    activity: crate::idle::Activity,
     graceful: kulfi_utils::Graceful,
     mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
     startup_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
-) {
+) -> Result<(), String> {
     use eyre::WrapErr;
+    use std::sync::atomic::Ordering;
 
+    let port = bound_port.load(Ordering::SeqCst);
     let socket = match tokio::net::UdpSocket::bind(format!("127.0.0.1:{port}"))
         .await
         .wrap_err_with(|| {
@@ -20,17 +29,18 @@ pub async fn udp_bridge(
         Ok(s) => Arc::new(s),
         Err(e) => {
             let error_msg = format!("Failed to bind UDP to port {port}: {e}");
-            eprintln!("{error_msg}");
-            let _ = startup_tx.send(Err(error_msg));
-            return;
+            tracing::error!("{error_msg}");
+            let _ = startup_tx.send(Err(error_msg.clone()));
+            return Err(error_msg);
         }
     };
 
     let local_addr = socket.local_addr().unwrap();
-    println!("UDP bridge listening on {local_addr}");
+    bound_port.store(local_addr.port(), Ordering::SeqCst);
+    tracing::info!("UDP bridge listening on {local_addr}");
     let _ = startup_tx.send(Ok(()));
 
-    let peer_connections = kulfi_utils::PeerStreamSenders::default();
+    let peer_connections = crate::pool::connections();
     let sessions: Arc<Mutex<HashMap<SocketAddr, tokio::sync::mpsc::Sender<Vec<u8>>>>> =
         Arc::new(Mutex::new(HashMap::new()));
 
@@ -44,6 +54,7 @@ pub async fn udp_bridge(
             result = socket.recv_from(&mut buf) => {
                 match result {
                     Ok((n, client_addr)) => {
+                        activity.touch();
                         let data = buf[..n].to_vec();
                         let mut sessions_guard = sessions.lock().await;
 
@@ -56,6 +67,8 @@ pub async fn udp_bridge(
                                     client_addr,
                                     data,
                                     proxy_target.clone(),
+                                    profile.clone(),
+                                    dialer.clone(),
                                     peer_connections.clone(),
                                     sessions.clone(),
                                     graceful.clone(),
@@ -68,26 +81,38 @@ pub async fn udp_bridge(
                                 client_addr,
                                 data,
                                 proxy_target.clone(),
+                                profile.clone(),
+                                dialer.clone(),
                                 peer_connections.clone(),
                                 sessions.clone(),
                                 graceful.clone(),
                             ).await;
                         }
                     }
+                    // an ICMP error for an earlier datagram, the socket itself is fine
+                    Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                        tracing::warn!("failed to recv UDP: {e:?}");
+                    }
                     Err(e) => {
                         tracing::error!("failed to recv UDP: {e:?}");
+                        return Err(format!("Failed to recv UDP: {e}"));
                     }
                 }
             }
         }
     }
+
+    Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn start_session(
     socket: Arc<tokio::net::UdpSocket>,
     client_addr: SocketAddr,
     initial_data: Vec<u8>,
     remote_node_id52: String,
+    profile: String,
+    dialer: crate::retry::Dialer,
     peer_connections: kulfi_utils::PeerStreamSenders,
     sessions: Arc<Mutex<HashMap<SocketAddr, tokio::sync::mpsc::Sender<Vec<u8>>>>>,
     graceful: kulfi_utils::Graceful,
@@ -100,63 +125,64 @@ async fn start_session(
     }
 
     let graceful_for_session = graceful.clone();
-    graceful.spawn(async move {
-        println!("forwarding UDP datagrams to {remote_node_id52}");
-
-        let self_endpoint = kulfi_utils::global_iroh_endpoint().await;
-        let header = kulfi_utils::ProtocolHeader::from(kulfi_utils::Protocol::Udp);
-
-        let result = async {
-            let (mut send, mut recv) = kulfi_utils::get_stream(
-                self_endpoint,
-                header,
-                remote_node_id52.to_string(),
-                peer_connections,
-                graceful_for_session.clone(),
-            )
-            .await?;
-
-            // Send the initial datagram
-            kulfi_utils::write_framed_datagram(&mut send, &initial_data).await?;
-
-            let socket_for_recv = socket.clone();
-
-            // iroh -> local UDP (responses from remote)
-            let recv_task = tokio::spawn(async move {
-                loop {
-                    match kulfi_utils::read_framed_datagram(&mut recv).await {
-                        Ok(data) => {
-                            if let Err(e) = socket_for_recv.send_to(&data, client_addr).await {
-                                tracing::error!("failed to send UDP response: {e:?}");
+    graceful.spawn(
+        async move {
+            tracing::info!("forwarding UDP datagrams to {remote_node_id52}");
+
+            let result = async {
+                let (mut send, mut recv) = dialer
+                    .get_stream(
+                        &profile,
+                        kulfi_utils::Protocol::Udp,
+                        &remote_node_id52,
+                        peer_connections,
+                        graceful_for_session.clone(),
+                    )
+                    .await?;
+
+                // Send the initial datagram
+                kulfi_utils::write_framed_datagram(&mut send, &initial_data).await?;
+
+                let socket_for_recv = socket.clone();
+
+                // iroh -> local UDP (responses from remote)
+                let recv_task = tokio::spawn(async move {
+                    loop {
+                        match kulfi_utils::read_framed_datagram(&mut recv).await {
+                            Ok(data) => {
+                                if let Err(e) = socket_for_recv.send_to(&data, client_addr).await {
+                                    tracing::error!("failed to send UDP response: {e:?}");
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                tracing::trace!("iroh recv stream ended: {e:?}");
                                 break;
                             }
                         }
-                        Err(e) => {
-                            tracing::trace!("iroh recv stream ended: {e:?}");
-                            break;
-                        }
                     }
+                });
+
+                // local UDP -> iroh (subsequent datagrams from client via channel)
+                while let Some(data) = rx.recv().await {
+                    kulfi_utils::write_framed_datagram(&mut send, &data).await?;
                 }
-            });
 
-            // local UDP -> iroh (subsequent datagrams from client via channel)
-            while let Some(data) = rx.recv().await {
-                kulfi_utils::write_framed_datagram(&mut send, &data).await?;
-            }
+                send.finish()?;
+                let _ = recv_task.await;
 
-            send.finish()?;
-            let _ = recv_task.await;
+                Ok::<(), eyre::Report>(())
+            }
+            .await;
 
-            Ok::<(), eyre::Report>(())
-        }
-        .await;
+            if let Err(e) = result {
+                tracing::error!("UDP session error: {e:?}");
+            }
 
-        if let Err(e) = result {
-            tracing::error!("UDP session error: {e:?}");
+            // Cleanup session
+            let mut sessions_guard = sessions.lock().await;
+            sessions_guard.remove(&client_addr);
         }
-
-        // Cleanup session
-        let mut sessions_guard = sessions.lock().await;
-        sessions_guard.remove(&client_addr);
-    });
+        .in_current_span(),
+    );
 }
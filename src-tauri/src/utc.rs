@@ -0,0 +1,113 @@
+/// A unix time broken down into its UTC calendar date and time of day, for log lines.
+#[derive(Debug, Clone, Copy)]
+pub struct Utc {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+}
+
+impl Utc {
+    pub fn from_unix_ms(ms: u64) -> Self {
+        let secs = ms / 1000;
+        let (days, rem) = ((secs / 86_400) as i64, (secs % 86_400) as u32);
+
+        // days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+
+        Utc {
+            year: yoe + era * 400 + i64::from(month <= 2),
+            month,
+            day,
+            hour: rem / 3_600,
+            minute: rem % 3_600 / 60,
+            second: rem % 60,
+            millis: (ms % 1000) as u32,
+        }
+    }
+
+    /// `YYYY-MM-DDTHH:MM:SS.mmmZ`
+    pub fn iso8601(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millis
+        )
+    }
+
+    /// `DD/Mon/YYYY:HH:MM:SS +0000` as in the Common Log Format.
+    pub fn clf(&self) -> String {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        format!(
+            "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iso8601(secs: u64) -> String {
+        Utc::from_unix_ms(secs * 1000).iso8601()
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(Utc::from_unix_ms(0).iso8601(), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn keeps_milliseconds() {
+        assert_eq!(
+            Utc::from_unix_ms(1_709_211_909_250).iso8601(),
+            "2024-02-29T13:05:09.250Z"
+        );
+    }
+
+    #[test]
+    fn leap_years() {
+        assert_eq!(iso8601(951_782_400), "2000-02-29T00:00:00.000Z");
+        assert_eq!(iso8601(951_868_800), "2000-03-01T00:00:00.000Z");
+        // 2100 is not a leap year
+        assert_eq!(iso8601(4_107_542_400 - 86_400), "2100-02-28T00:00:00.000Z");
+        assert_eq!(iso8601(4_107_542_400), "2100-03-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn year_boundaries() {
+        assert_eq!(iso8601(946_684_799), "1999-12-31T23:59:59.000Z");
+        assert_eq!(iso8601(946_684_800), "2000-01-01T00:00:00.000Z");
+        assert_eq!(iso8601(2_147_483_648), "2038-01-19T03:14:08.000Z");
+    }
+
+    #[test]
+    fn clf() {
+        assert_eq!(
+            Utc::from_unix_ms(1_709_211_909_250).clf(),
+            "29/Feb/2024:13:05:09 +0000"
+        );
+        assert_eq!(Utc::from_unix_ms(0).clf(), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(
+            Utc::from_unix_ms(946_684_799_000).clf(),
+            "31/Dec/1999:23:59:59 +0000"
+        );
+    }
+}